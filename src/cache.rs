@@ -0,0 +1,124 @@
+//! A local ETag cache for `--cache-dir`.
+//!
+//! Each URL gets an `<md5>.etag` and `<md5>.body` file, plus the status and
+//! headers in `<md5>.headers` for `--offline`. The stored ETag is sent as
+//! `If-None-Match`, and a 304 answer is served from the stored body.
+
+use crate::error::{self, FetchError};
+use crate::output::Printer;
+use reqwest::StatusCode;
+use reqwest::header::{ETAG, HeaderMap, HeaderName, HeaderValue};
+use std::path::PathBuf;
+
+/// The cache entry for a single URL
+pub struct Cache {
+    dir: PathBuf,
+    key: String,
+}
+
+impl Cache {
+    pub fn new(dir: &str, url: &reqwest::Url) -> Self {
+        Cache {
+            dir: PathBuf::from(dir),
+            key: format!("{:x}", md5::compute(url.as_str())),
+        }
+    }
+
+    fn path(&self, extension: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", self.key, extension))
+    }
+
+    /// The stored ETag, if this URL has been cached.
+    pub fn etag(&self) -> Option<String> {
+        let etag = std::fs::read_to_string(self.path("etag")).ok()?;
+        self.path("body").exists().then_some(etag)
+    }
+
+    /// The stored status, headers, and body, for `--offline`.
+    ///
+    /// Entries cached before headers were stored come back as a plain 200.
+    pub fn response(&self) -> Option<(StatusCode, HeaderMap, Vec<u8>)> {
+        let body = std::fs::read(self.path("body")).ok()?;
+        let stored = std::fs::read_to_string(self.path("headers")).unwrap_or_default();
+        let mut lines = stored.lines();
+        let status = lines
+            .next()
+            .and_then(|line| StatusCode::from_bytes(line.trim().as_bytes()).ok())
+            .unwrap_or(StatusCode::OK);
+        let mut headers = HeaderMap::new();
+        for line in lines {
+            let Some((name, value)) = line.split_once(':') else {
+                continue;
+            };
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::from_bytes(name.trim().as_bytes()),
+                HeaderValue::from_str(value.trim()),
+            ) {
+                headers.append(name, value);
+            }
+        }
+        Some((status, headers, body))
+    }
+
+    /// Serves a 304 from the cache and refreshes the entry on success.
+    ///
+    /// Returns the body to show: the cached one for a 304, otherwise the
+    /// body that was received.
+    pub fn update(
+        &self,
+        status: StatusCode,
+        headers: &HeaderMap,
+        body: Vec<u8>,
+        printer: &mut Printer,
+    ) -> error::Result<Vec<u8>> {
+        if status == StatusCode::NOT_MODIFIED {
+            let path = self.path("body");
+            return match std::fs::read(&path) {
+                Ok(cached) => {
+                    errln!(printer, "Cache hit: serving the stored body");
+                    Ok(cached)
+                }
+                Err(_) => Ok(body),
+            };
+        }
+        if !status.is_success() {
+            return Ok(body);
+        }
+
+        match headers.get(ETAG).and_then(|etag| etag.to_str().ok()) {
+            // A new ETag replaces whatever was stored before
+            Some(etag) => self.store(etag, status, headers, &body)?,
+            None => {
+                for extension in ["etag", "body", "headers"] {
+                    let _ = std::fs::remove_file(self.path(extension));
+                }
+            }
+        }
+        Ok(body)
+    }
+
+    fn store(
+        &self,
+        etag: &str,
+        status: StatusCode,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> error::Result<()> {
+        let write = |path: PathBuf, contents: &[u8]| {
+            std::fs::write(&path, contents)
+                .map_err(|e| FetchError::write(&path.display().to_string(), e))
+        };
+        std::fs::create_dir_all(&self.dir)
+            .map_err(|e| FetchError::write(&self.dir.display().to_string(), e))?;
+        // The status code, then one "name: value" line per header
+        let mut stored = format!("{}\n", status.as_u16());
+        for (name, value) in headers {
+            if let Ok(value) = value.to_str() {
+                stored.push_str(&format!("{}: {}\n", name, value));
+            }
+        }
+        write(self.path("body"), body)?;
+        write(self.path("headers"), stored.as_bytes())?;
+        write(self.path("etag"), etag.as_bytes())
+    }
+}
@@ -0,0 +1,78 @@
+//! ANSI syntax highlighting for JSON response bodies.
+
+use serde_json::Value;
+
+const RESET: &str = "\x1b[0m";
+const KEY: &str = "\x1b[34m";
+const STRING: &str = "\x1b[32m";
+const NUMBER: &str = "\x1b[36m";
+const BOOLEAN: &str = "\x1b[33m";
+const NULL: &str = "\x1b[35m";
+
+/// Renders a JSON value with ANSI colors, indented like
+/// `serde_json::to_string_pretty` when `pretty` is set and minified otherwise.
+pub fn colorize_json(value: &Value, pretty: bool) -> String {
+    let mut out = String::new();
+    write_value(&mut out, value, pretty, 0);
+    out
+}
+
+fn write_value(out: &mut String, value: &Value, pretty: bool, depth: usize) {
+    match value {
+        Value::Null => paint(out, NULL, "null"),
+        Value::Bool(b) => paint(out, BOOLEAN, &b.to_string()),
+        Value::Number(n) => paint(out, NUMBER, &n.to_string()),
+        Value::String(s) => paint(out, STRING, &Value::String(s.clone()).to_string()),
+        Value::Array(items) => {
+            write_container(out, '[', ']', items.len(), pretty, depth, |out, i| {
+                write_value(out, &items[i], pretty, depth + 1);
+            });
+        }
+        Value::Object(map) => {
+            let entries: Vec<_> = map.iter().collect();
+            write_container(out, '{', '}', entries.len(), pretty, depth, |out, i| {
+                let (key, item) = entries[i];
+                paint(out, KEY, &Value::String(key.clone()).to_string());
+                out.push_str(if pretty { ": " } else { ":" });
+                write_value(out, item, pretty, depth + 1);
+            });
+        }
+    }
+}
+
+fn write_container(
+    out: &mut String,
+    open: char,
+    close: char,
+    len: usize,
+    pretty: bool,
+    depth: usize,
+    mut write_item: impl FnMut(&mut String, usize),
+) {
+    out.push(open);
+    if len == 0 {
+        out.push(close);
+        return;
+    }
+    for i in 0..len {
+        if i > 0 {
+            out.push(',');
+        }
+        if pretty {
+            out.push('\n');
+            out.push_str(&"  ".repeat(depth + 1));
+        }
+        write_item(out, i);
+    }
+    if pretty {
+        out.push('\n');
+        out.push_str(&"  ".repeat(depth));
+    }
+    out.push(close);
+}
+
+fn paint(out: &mut String, color: &str, text: &str) {
+    out.push_str(color);
+    out.push_str(text);
+    out.push_str(RESET);
+}
@@ -0,0 +1,127 @@
+//! Global defaults loaded from a config file.
+//!
+//! Values here only fill in what the command line leaves unset, so any flag
+//! passed explicitly still wins. Named environments under `[env.<name>]`
+//! override the top-level values when selected with `--env`.
+
+use crate::Cli;
+use crate::error::{self, FetchError};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Default for `--timeout`, in seconds
+    pub timeout: Option<f64>,
+    /// Headers sent with every request, before `--header-file` and `--header`
+    #[serde(default, alias = "headers")]
+    pub header: Vec<String>,
+    /// Default for `--user-agent`
+    pub user_agent: Option<String>,
+    /// Default for `--proxy`
+    pub proxy: Option<String>,
+    /// Turns on `--follow` when set to true
+    pub follow: Option<bool>,
+    /// Default for `--base-url`
+    pub base_url: Option<String>,
+    /// Default for `--bearer`, used when no auth flag is given
+    pub bearer: Option<String>,
+    /// Default for `--user`, used when no auth flag is given
+    pub user: Option<String>,
+    /// Named environments, selected with `--env`
+    #[serde(default)]
+    pub env: BTreeMap<String, Config>,
+}
+
+impl Config {
+    /// Layers the environment `name` over the top-level defaults.
+    pub fn select(mut self, name: &str) -> error::Result<Config> {
+        let Some(profile) = self.env.remove(name) else {
+            let available: Vec<_> = self.env.keys().map(String::as_str).collect();
+            return Err(FetchError::Usage(if available.is_empty() {
+                format!("Unknown environment {:?}: the config defines none", name)
+            } else {
+                format!(
+                    "Unknown environment {:?}, expected one of: {}",
+                    name,
+                    available.join(", ")
+                )
+            }));
+        };
+
+        let mut header = self.header;
+        header.extend(profile.header);
+        // Credentials come as a pair, so a profile's auth replaces both
+        let (bearer, user) = if profile.bearer.is_some() || profile.user.is_some() {
+            (profile.bearer, profile.user)
+        } else {
+            (self.bearer, self.user)
+        };
+        Ok(Config {
+            timeout: profile.timeout.or(self.timeout),
+            header,
+            user_agent: profile.user_agent.or(self.user_agent),
+            proxy: profile.proxy.or(self.proxy),
+            follow: profile.follow.or(self.follow),
+            base_url: profile.base_url.or(self.base_url),
+            bearer,
+            user,
+            env: BTreeMap::new(),
+        })
+    }
+
+    /// Fills in CLI options that weren't given on the command line.
+    pub fn apply(&self, cli: &mut Cli) {
+        cli.timeout = cli.timeout.or(self.timeout);
+        cli.user_agent = cli.user_agent.take().or_else(|| self.user_agent.clone());
+        cli.proxy = cli.proxy.take().or_else(|| self.proxy.clone());
+        cli.follow |= self.follow.unwrap_or(false);
+        cli.base_url = cli.base_url.take().or_else(|| self.base_url.clone());
+
+        // Any auth flag on the command line replaces the configured auth
+        let explicit_auth = cli.bearer.is_some()
+            || cli.user.is_some()
+            || cli.digest.is_some()
+            || cli.aws_sigv4.is_some()
+            || cli.netrc
+            || cli.netrc_file.is_some();
+        if !explicit_auth {
+            cli.bearer = self.bearer.clone();
+            if cli.bearer.is_none() {
+                cli.user = self.user.clone();
+            }
+            cli.auth_from_config = cli.bearer.is_some() || cli.user.is_some();
+        }
+    }
+}
+
+/// `$XDG_CONFIG_HOME/fetch/config.toml`, falling back to `~/.config`.
+fn default_path() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("fetch").join("config.toml"))
+}
+
+/// Loads the config from `path`, or from the default location if no path is
+/// given. A missing default config is treated as empty.
+pub fn load(path: Option<&str>) -> error::Result<Config> {
+    let path = match path {
+        Some(path) => PathBuf::from(path),
+        None => match default_path() {
+            Some(path) if path.exists() => path,
+            _ => return Ok(Config::default()),
+        },
+    };
+    let display = path.display().to_string();
+    let content = std::fs::read_to_string(&path).map_err(|e| FetchError::read(&display, e))?;
+
+    let parsed = if path.extension().is_some_and(|ext| ext == "json") {
+        serde_json::from_str(&content).map_err(|e| e.to_string())
+    } else {
+        toml::from_str(&content).map_err(|e| e.to_string())
+    };
+    parsed.map_err(|e| FetchError::Parse(format!("Invalid config {}: {}", display, e)))
+}
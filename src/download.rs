@@ -0,0 +1,112 @@
+//! Writing `--output` bodies through a `<path>.partial` file.
+//!
+//! Chunks land in the partial file as they arrive, and it is renamed into
+//! place only once the body is complete, so an interrupted download never
+//! leaves a truncated file at the real path. What did arrive stays in the
+//! partial file for `--continue-at` to pick up.
+
+use crate::error::{self, FetchError};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Partial files of downloads that haven't finished yet
+static UNFINISHED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// A body being written to `<path>.partial`
+pub struct Download {
+    path: String,
+    partial: String,
+    file: File,
+}
+
+impl Download {
+    /// Starts a download of `path`, discarding any earlier partial file.
+    pub fn create(path: &str) -> error::Result<Self> {
+        let partial = partial_path(path);
+        let file = File::create(&partial).map_err(|e| FetchError::write(&partial, e))?;
+        Ok(Self::started(path, partial, file))
+    }
+
+    /// Continues an earlier download of `path`, appending to its partial
+    /// file. A complete file from elsewhere becomes the partial file.
+    pub fn resume(path: &str) -> error::Result<Self> {
+        let partial = partial_path(path);
+        if !Path::new(&partial).exists() && Path::new(path).exists() {
+            std::fs::rename(path, &partial).map_err(|e| FetchError::write(&partial, e))?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&partial)
+            .map_err(|e| FetchError::write(&partial, e))?;
+        Ok(Self::started(path, partial, file))
+    }
+
+    fn started(path: &str, partial: String, file: File) -> Self {
+        let mut unfinished = UNFINISHED.lock().expect("download list poisoned");
+        if !unfinished.contains(&partial) {
+            unfinished.push(partial.clone());
+        }
+        Download {
+            path: path.to_string(),
+            partial,
+            file,
+        }
+    }
+
+    /// Where the body is written until it is complete.
+    pub fn partial(&self) -> &str {
+        &self.partial
+    }
+
+    pub fn write(&mut self, chunk: &[u8]) -> error::Result<()> {
+        self.file
+            .write_all(chunk)
+            .map_err(|e| FetchError::write(&self.partial, e))
+    }
+
+    /// Moves the completed body into place at `path`.
+    pub fn finish(self) -> error::Result<()> {
+        self.file
+            .sync_all()
+            .map_err(|e| FetchError::write(&self.partial, e))?;
+        std::fs::rename(&self.partial, &self.path).map_err(|e| FetchError::write(&self.path, e))?;
+        UNFINISHED
+            .lock()
+            .expect("download list poisoned")
+            .retain(|partial| *partial != self.partial);
+        Ok(())
+    }
+}
+
+/// Writes a body that is already in memory to `path`, through the partial
+/// file like a streamed one.
+pub fn write(path: &str, bytes: &[u8]) -> error::Result<()> {
+    let mut download = Download::create(path)?;
+    download.write(bytes)?;
+    download.finish()
+}
+
+/// How much of `path` is already downloaded: the size of its partial file,
+/// or of the file itself.
+pub fn existing_len(path: &str) -> error::Result<u64> {
+    for candidate in [partial_path(path), path.to_string()] {
+        match std::fs::metadata(&candidate) {
+            Ok(metadata) => return Ok(metadata.len()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(FetchError::read(&candidate, e)),
+        }
+    }
+    Ok(0)
+}
+
+/// The partial files of downloads cut short so far, e.g. by Ctrl-C.
+pub fn unfinished() -> Vec<String> {
+    UNFINISHED.lock().expect("download list poisoned").clone()
+}
+
+fn partial_path(path: &str) -> String {
+    format!("{}.partial", path)
+}
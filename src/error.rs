@@ -0,0 +1,122 @@
+//! The error type shared by every step of building, sending, and printing a
+//! request.
+
+use std::fmt;
+
+/// Everything that can make a fetch fail, with a user-facing message.
+#[derive(Debug)]
+pub enum FetchError {
+    /// Conflicting or invalid command-line options
+    Usage(String),
+    /// A file that fetch needed could not be read
+    FileRead {
+        path: String,
+        source: std::io::Error,
+    },
+    /// A file that fetch produces could not be written
+    FileWrite {
+        path: String,
+        source: std::io::Error,
+    },
+    /// A request file, JSON argument, or similar input is malformed
+    Parse(String),
+    /// A header could not be turned into a valid HTTP header
+    InvalidHeader { header: String, reason: String },
+    /// reqwest rejected the request before sending it (e.g., a bad URL)
+    RequestBuild(reqwest::Error),
+    /// The request did not complete within `--timeout`
+    Timeout(f64),
+    /// No connection could be made within `--connect-timeout`
+    ConnectTimeout(f64),
+    /// Connecting, sending, or reading the response failed
+    Network(reqwest::Error),
+    /// The response arrived but could not be handled as requested
+    Response(String),
+    /// One or more `--expect-*` checks did not hold
+    Expectation(Vec<String>),
+}
+
+impl FetchError {
+    /// The process exit code for this error. Codes shared with curl keep the
+    /// same meaning (7, 23, 26, 28); the rest are fetch-specific.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            FetchError::Response(_) => 1,
+            FetchError::Usage(_) => 2,
+            FetchError::Parse(_) => 3,
+            FetchError::InvalidHeader { .. } => 4,
+            FetchError::RequestBuild(_) => 5,
+            FetchError::Network(_) => 7,
+            FetchError::Expectation(_) => 8,
+            FetchError::FileWrite { .. } => 23,
+            FetchError::FileRead { .. } => 26,
+            FetchError::Timeout(_) | FetchError::ConnectTimeout(_) => 28,
+        }
+    }
+
+    /// Shorthand for a `FileRead` error on `path`.
+    pub fn read(path: &str, source: std::io::Error) -> Self {
+        FetchError::FileRead {
+            path: path.to_string(),
+            source,
+        }
+    }
+
+    /// Shorthand for a `FileWrite` error on `path`.
+    pub fn write(path: &str, source: std::io::Error) -> Self {
+        FetchError::FileWrite {
+            path: path.to_string(),
+            source,
+        }
+    }
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FetchError::Usage(msg) | FetchError::Parse(msg) | FetchError::Response(msg) => {
+                write!(f, "{}", msg)
+            }
+            FetchError::FileRead { path, source } => write!(f, "Cannot read {}: {}", path, source),
+            FetchError::FileWrite { path, source } => {
+                write!(f, "Cannot write {}: {}", path, source)
+            }
+            FetchError::InvalidHeader { header, reason } => {
+                write!(f, "Invalid header {:?}: {}", header, reason)
+            }
+            FetchError::RequestBuild(e) => write!(f, "Cannot build request: {}", e),
+            FetchError::Timeout(secs) => write!(f, "Request timed out after {}s", secs),
+            FetchError::ConnectTimeout(secs) => {
+                write!(f, "Could not connect within {}s (--connect-timeout)", secs)
+            }
+            FetchError::Network(e) => write!(f, "{}", e),
+            FetchError::Expectation(failures) => {
+                write!(f, "Expectation failed: {}", failures.join("; "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for FetchError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FetchError::FileRead { source, .. } | FetchError::FileWrite { source, .. } => {
+                Some(source)
+            }
+            FetchError::RequestBuild(e) | FetchError::Network(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for FetchError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_builder() {
+            FetchError::RequestBuild(e)
+        } else {
+            FetchError::Network(e)
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, FetchError>;
@@ -0,0 +1,126 @@
+//! Recording requests and responses for `--har` as an HTTP Archive (HAR
+//! 1.2) log.
+//!
+//! Entries from every request, including concurrent ones, collect in one
+//! process-wide log that is written out once all requests have finished.
+
+use crate::error::{self, FetchError};
+use reqwest::StatusCode;
+use reqwest::header::{CONTENT_TYPE, HeaderMap};
+use serde_json::{Value, json};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+static ENTRIES: Mutex<Vec<Value>> = Mutex::new(Vec::new());
+
+/// The request half of an entry, captured before the request is sent
+pub struct Request {
+    started: SystemTime,
+    request: Value,
+}
+
+impl Request {
+    pub fn new(request: &reqwest::Request) -> Self {
+        let body = request.body().and_then(|body| body.as_bytes());
+        let mut value = json!({
+            "method": request.method().as_str(),
+            "url": request.url().as_str(),
+            "httpVersion": format!("{:?}", request.version()),
+            "cookies": [],
+            "headers": headers(request.headers()),
+            "queryString": request
+                .url()
+                .query_pairs()
+                .map(|(name, value)| json!({ "name": name, "value": value }))
+                .collect::<Vec<_>>(),
+            "headersSize": -1,
+            "bodySize": body.map_or(-1, |body| body.len() as i64),
+        });
+        if let Some(body) = body.filter(|body| !body.is_empty()) {
+            value["postData"] = json!({
+                "mimeType": mime_type(request.headers()),
+                "text": String::from_utf8_lossy(body),
+            });
+        }
+        Request {
+            started: SystemTime::now(),
+            request: value,
+        }
+    }
+
+    /// Adds the finished exchange to the log. `body` is `None` when the
+    /// response body wasn't read.
+    pub fn record(
+        &self,
+        status: StatusCode,
+        version: reqwest::Version,
+        response_headers: &HeaderMap,
+        body: Option<&[u8]>,
+        time: Duration,
+    ) {
+        let millis = time.as_secs_f64() * 1000.0;
+        let size = body.map_or(-1, |body| body.len() as i64);
+        let entry = json!({
+            "startedDateTime": crate::log::rfc3339(self.started),
+            "time": millis,
+            "request": self.request.clone(),
+            "response": {
+                "status": status.as_u16(),
+                "statusText": status.canonical_reason().unwrap_or(""),
+                "httpVersion": format!("{:?}", version),
+                "cookies": [],
+                "headers": headers(response_headers),
+                "content": {
+                    "size": size,
+                    "mimeType": mime_type(response_headers),
+                    "text": body.map(String::from_utf8_lossy).unwrap_or_default(),
+                },
+                "redirectURL": response_headers
+                    .get(reqwest::header::LOCATION)
+                    .and_then(|location| location.to_str().ok())
+                    .unwrap_or(""),
+                "headersSize": -1,
+                "bodySize": size,
+            },
+            "cache": {},
+            "timings": { "send": 0, "wait": millis, "receive": 0 },
+        });
+        ENTRIES.lock().expect("HAR log poisoned").push(entry);
+    }
+}
+
+/// Writes every recorded entry to `path` as a HAR log.
+pub fn write(path: &str) -> error::Result<()> {
+    let entries = std::mem::take(&mut *ENTRIES.lock().expect("HAR log poisoned"));
+    let har = json!({
+        "log": {
+            "version": "1.2",
+            "creator": { "name": "fetch", "version": env!("CARGO_PKG_VERSION") },
+            "entries": entries,
+        }
+    });
+    let content = serde_json::to_string_pretty(&har).expect("HAR is valid JSON");
+    std::fs::write(path, content).map_err(|e| FetchError::write(path, e))
+}
+
+/// HAR name/value pairs, with credentials redacted like `--log-file`
+fn headers(headers: &HeaderMap) -> Vec<Value> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if crate::log::is_secret(name, value) {
+                "<redacted>".into()
+            } else {
+                String::from_utf8_lossy(value.as_bytes())
+            };
+            json!({ "name": name.as_str(), "value": value })
+        })
+        .collect()
+}
+
+fn mime_type(headers: &HeaderMap) -> &str {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("")
+}
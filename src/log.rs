@@ -0,0 +1,135 @@
+//! The `--log-file` audit log: one JSON line appended per request sent.
+
+use crate::error::{self, FetchError};
+use reqwest::StatusCode;
+use reqwest::header::{HeaderName, HeaderValue};
+use std::io::Write;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Headers that carry credentials even when reqwest doesn't mark them
+const SECRET_HEADERS: [&str; 3] = ["authorization", "proxy-authorization", "cookie"];
+
+/// What is known about a request before it is sent
+pub struct Entry {
+    timestamp: SystemTime,
+    method: String,
+    url: String,
+    headers: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Entry {
+    /// Captures the request, redacting credentials unless `secrets` is set.
+    pub fn new(request: &reqwest::Request, secrets: bool) -> Self {
+        let mut headers = serde_json::Map::new();
+        for (name, value) in request.headers() {
+            let value = if is_secret(name, value) && !secrets {
+                "<redacted>".to_string()
+            } else {
+                String::from_utf8_lossy(value.as_bytes()).into_owned()
+            };
+            headers.insert(name.to_string(), value.into());
+        }
+        Entry {
+            timestamp: SystemTime::now(),
+            method: request.method().to_string(),
+            url: request.url().to_string(),
+            headers,
+        }
+    }
+
+    /// Appends the finished request to the log at `path`.
+    ///
+    /// `status` and `size` are `None` when the request failed or the body
+    /// wasn't read; `error` says why a request failed.
+    pub fn write(
+        &self,
+        path: &str,
+        status: Option<StatusCode>,
+        size: Option<usize>,
+        duration: Duration,
+        error: Option<&str>,
+    ) -> error::Result<()> {
+        let mut line = serde_json::json!({
+            "timestamp": rfc3339(self.timestamp),
+            "method": self.method,
+            "url": self.url,
+            "headers": self.headers,
+            "status": status.map(|status| status.as_u16()),
+            "duration_ms": duration.as_millis() as u64,
+            "size": size,
+        });
+        if let Some(error) = error {
+            line["error"] = error.into();
+        }
+
+        // One write per line keeps concurrent requests from interleaving
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| FetchError::write(path, e))?;
+        file.write_all(format!("{}\n", line).as_bytes())
+            .map_err(|e| FetchError::write(path, e))
+    }
+}
+
+/// Whether a header carries credentials that shouldn't be written out.
+pub fn is_secret(name: &HeaderName, value: &HeaderValue) -> bool {
+    value.is_sensitive() || SECRET_HEADERS.contains(&name.as_str())
+}
+
+/// Formats a time as UTC, e.g. `2024-05-01T12:30:00.123Z`.
+pub fn rfc3339(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let (days, of_day) = (seconds / 86_400, seconds % 86_400);
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        of_day / 3_600,
+        of_day % 3_600 / 60,
+        of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn at(seconds: u64, millis: u64) -> String {
+        rfc3339(UNIX_EPOCH + Duration::from_secs(seconds) + Duration::from_millis(millis))
+    }
+
+    #[test]
+    fn formats_epoch() {
+        assert_eq!(at(0, 0), "1970-01-01T00:00:00.000Z");
+    }
+
+    #[test]
+    fn formats_leap_days() {
+        assert_eq!(at(1_709_208_000, 123), "2024-02-29T12:00:00.123Z");
+        assert_eq!(at(951_782_400, 0), "2000-02-29T00:00:00.000Z");
+    }
+
+    #[test]
+    fn formats_end_of_year() {
+        assert_eq!(at(1_704_067_199, 999), "2023-12-31T23:59:59.999Z");
+        assert_eq!(at(1_704_067_200, 0), "2024-01-01T00:00:00.000Z");
+    }
+}
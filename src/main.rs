@@ -1,14 +1,115 @@
-use clap::{ArgGroup, Parser};
-use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
-use serde::Deserialize;
+#[macro_use]
+mod output;
+
+mod cache;
+mod color;
+mod config;
+mod digest;
+mod download;
+mod error;
+mod har;
+mod hexdump;
+mod log;
+mod markup;
+mod netrc;
+mod patch;
+mod rate_limit;
+mod repl;
+mod request_file;
+mod sigv4;
+mod template;
+
+use clap::{ArgGroup, Parser, ValueEnum};
+use encoding_rs::{Encoding, UTF_8};
+use error::FetchError;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use output::Printer;
+use request_file::{RequestFile, RequestFileContent};
+use reqwest::header::{
+    ACCEPT, AUTHORIZATION, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE,
+    HeaderMap, HeaderName, HeaderValue, IF_NONE_MATCH, RANGE, RETRY_AFTER, USER_AGENT,
+    WWW_AUTHENTICATE,
+};
+use reqwest::redirect::Policy;
+use reqwest::{Method, StatusCode};
+use reqwest_cookie_store::{CookieStore, CookieStoreMutex};
+use std::hash::{BuildHasher, RandomState};
+use std::io::{IsTerminal, Write};
 use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
 
-#[derive(Debug, Deserialize)]
-struct RequestFile {
+/// A request resolved from either a request file or the CLI flags
+#[derive(Clone)]
+struct RequestSpec {
+    name: Option<String>,
     url: String,
     method: String,
-    body: Option<serde_json::Value>,
-    headers: Option<Vec<String>>,
+    body: Option<Vec<u8>>,
+    headers: Vec<String>,
+    query: Vec<(String, String)>,
+    /// Where to save the response body instead of printing it
+    output: Option<String>,
+    /// File streamed as the body instead of being read into memory
+    body_file: Option<String>,
+}
+
+impl From<RequestFile> for RequestSpec {
+    fn from(file: RequestFile) -> Self {
+        RequestSpec {
+            name: file.name,
+            url: file.url,
+            method: file.method.to_uppercase(),
+            body: file
+                .body_contents
+                .or_else(|| file.body.map(|v| v.to_string().into_bytes())),
+            headers: file.headers.unwrap_or_default(),
+            query: Vec::new(),
+            output: None,
+            body_file: None,
+        }
+    }
+}
+
+/// How the response body is printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// Pretty-print JSON bodies, print anything else as-is
+    Auto,
+    /// Never reformat the body
+    Raw,
+    /// Pretty-print JSON, failing if the body isn't JSON
+    Pretty,
+    /// Minify JSON, failing if the body isn't JSON
+    Compact,
+}
+
+/// When to highlight JSON output with ANSI colors
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ColorChoice {
+    /// Color only when stdout is a terminal
+    Auto,
+    Always,
+    Never,
+}
+
+/// Which parts of the exchange `--print` shows
+#[derive(Debug, Clone, Copy)]
+struct PrintSpec {
+    request_headers: bool,
+    request_body: bool,
+    response_headers: bool,
+    response_body: bool,
+}
+
+/// Where `--continue-at` resumes a download from
+#[derive(Debug, Clone, Copy)]
+enum ContinueAt {
+    /// `-`: the size of the existing --output file
+    Auto,
+    Offset(u64),
 }
 
 /// Simple HTTP client like curl
@@ -17,96 +118,2781 @@ struct RequestFile {
 #[command(about = "A basic HTTP client CLI", long_about = None)]
 #[command(group(
     ArgGroup::new("input")
-        .args(["file", "host"])
+        .args(["file", "host", "hosts_file", "interactive"])
         .required(true)
 ))]
+#[command(group(
+    ArgGroup::new("repeating")
+        .args(["repeat", "repeat_until"])
+        .multiple(true)
+))]
 struct Cli {
-    /// Path to JSON file describing the request
+    /// Path to a JSON, YAML, or TOML file describing the request. `${VAR}`
+    /// placeholders are replaced with environment variables
     #[arg(long)]
     file: Option<String>,
 
-    /// Target host URL (e.g., https://example.com)
+    /// Target host URL (e.g., https://example.com), or a path joined onto
+    /// --base-url
     #[arg(long)]
     host: Option<String>,
 
-    /// HTTP method: GET, POST, PUT, DELETE (default: GET)
+    /// File of URLs, one per line, that each get the request built from the
+    /// other flags. Blank lines and `#` comments are skipped
+    #[arg(long)]
+    hosts_file: Option<String>,
+
+    /// Read requests like `GET /users/1` or `POST /users {...}` from stdin
+    /// one line at a time, keeping cookies and session headers between
+    /// them. Paths are joined onto --base-url; type `help` for commands
+    #[arg(long)]
+    interactive: bool,
+
+    /// Base URL that relative --host values and request file URLs are joined
+    /// onto (e.g., https://api.example.com/v1/)
+    #[arg(long)]
+    base_url: Option<String>,
+
+    /// HTTP method, e.g. GET, POST, DELETE, or a custom one like PROPFIND
     #[arg(long, default_value = "GET")]
     method: String,
 
-    /// HTTP body as a string (optional)
+    /// Send the request as POST with an `X-HTTP-Method-Override: <METHOD>`
+    /// header, for backends that only accept POST. Replaces --method, and
+    /// the method of every request in a --file
+    #[arg(long, conflicts_with = "method")]
+    method_override: Option<String>,
+
+    /// HTTP body as a string (optional). Use "-" to read it from stdin,
+    /// e.g. `cat payload.json | fetch --host ... --method POST --body -`,
+    /// or "@path" to read it from a file ("\@" for a literal "@")
     #[arg(long)]
     body: Option<String>,
 
-    /// Optional headers in the form "Key: Value" (can be repeated)
+    /// Optional headers in the form "Key: Value" (can be repeated).
+    /// Repeating a name sends every value, e.g. two Accept headers; a name
+    /// set here still replaces the same header from --header-file or the
+    /// config
     #[arg(long)]
     header: Vec<String>,
+
+    /// Set a header from an environment variable, as "Name=VAR" (can be
+    /// repeated), keeping secrets like tokens out of shell history. A
+    /// --header with the same name still wins
+    #[arg(long, value_name = "NAME=VAR", value_parser = parse_key_value)]
+    header_from_env: Vec<(String, String)>,
+
+    /// Let a repeated --header name replace the earlier value instead of
+    /// sending both
+    #[arg(long)]
+    replace_headers: bool,
+
+    /// Write the raw response body to this file instead of printing it
+    #[arg(long)]
+    output: Option<String>,
+
+    /// Resume an interrupted --output download from "-", the end of what
+    /// `<path>.partial` (or the file itself) already holds, or from a byte
+    /// offset, which must be that same length. A 206 response is appended
+    /// and the file moved into place; a server that ignores the range
+    /// overwrites it
+    #[arg(long, value_name = "OFFSET", value_parser = parse_continue_at, requires = "output", conflicts_with_all = ["compressed", "cache_dir", "paginate"])]
+    continue_at: Option<ContinueAt>,
+
+    /// Save each response body to its own file in this directory, named
+    /// `<index>-<name>.txt`, or `<index>-<method>.txt` for unnamed requests
+    #[arg(long, conflicts_with_all = ["output", "paginate", "head_only"])]
+    output_dir: Option<String>,
+
+    /// Write the status line and response headers to this file as raw
+    /// HTTP lines, one block per response
+    #[arg(long)]
+    output_headers: Option<String>,
+
+    /// Give up on the request after this many seconds (e.g., 2.5). This is
+    /// the whole exchange, connecting included, so it also caps
+    /// --connect-timeout
+    #[arg(long, visible_alias = "max-time", value_parser = parse_seconds)]
+    timeout: Option<f64>,
+
+    /// Give up if the connection (DNS, TCP, and TLS) isn't up after this
+    /// many seconds. Leaves the rest of the request to --timeout
+    #[arg(long, value_parser = parse_seconds)]
+    connect_timeout: Option<f64>,
+
+    /// Include the response headers in the output
+    #[arg(short, long)]
+    include: bool,
+
+    /// Bearer token sent as "Authorization: Bearer <TOKEN>"
+    #[arg(long)]
+    bearer: Option<String>,
+
+    /// Basic auth credentials as "username:password"
+    #[arg(long, conflicts_with = "bearer")]
+    user: Option<String>,
+
+    /// Digest auth credentials as "username:password", answering the
+    /// server's challenge with MD5
+    #[arg(long, conflicts_with_all = ["bearer", "user"])]
+    digest: Option<String>,
+
+    /// Sign the request with AWS Signature Version 4 for "region/service",
+    /// e.g. "us-east-1/s3". Credentials come from --aws-access-key and
+    /// --aws-secret-key or the AWS_ACCESS_KEY_ID, AWS_SECRET_ACCESS_KEY, and
+    /// AWS_SESSION_TOKEN environment variables
+    #[arg(long, value_name = "REGION/SERVICE", conflicts_with_all = ["bearer", "user", "digest"])]
+    aws_sigv4: Option<String>,
+
+    /// AWS access key ID for --aws-sigv4
+    #[arg(long, requires = "aws_sigv4")]
+    aws_access_key: Option<String>,
+
+    /// AWS secret access key for --aws-sigv4
+    #[arg(long, requires = "aws_sigv4")]
+    aws_secret_key: Option<String>,
+
+    /// Send basic auth from the ~/.netrc entry for the request's host.
+    /// --user, --bearer, --digest, and an Authorization header take
+    /// precedence
+    #[arg(long)]
+    netrc: bool,
+
+    /// Read --netrc credentials from this file instead of ~/.netrc
+    #[arg(long)]
+    netrc_file: Option<String>,
+
+    /// Follow redirects instead of returning the 3xx response as-is
+    #[arg(short = 'L', long)]
+    follow: bool,
+
+    /// Maximum number of redirects to follow with --follow
+    #[arg(long, default_value_t = 10, requires = "follow")]
+    max_redirects: usize,
+
+    /// Query parameters in the form "key=value" (can be repeated)
+    #[arg(long, value_parser = parse_key_value)]
+    query: Vec<(String, String)>,
+
+    /// Form fields in the form "key=value", sent urlencoded (can be repeated)
+    #[arg(long, value_parser = parse_key_value, conflicts_with = "body")]
+    form: Vec<(String, String)>,
+
+    /// Stream the HTTP body from this file as raw bytes, without loading it
+    /// into memory. Streamed bodies aren't retried
+    #[arg(long, conflicts_with_all = ["body", "form"])]
+    body_file: Option<String>,
+
+    /// Print the request line, headers, and body to stderr before sending
+    #[arg(short, long)]
+    verbose: bool,
+
+    /// Exit with code 22 and skip the body when the status is 400 or above
+    #[arg(short, long)]
+    fail: bool,
+
+    /// Like --fail, but print (or save with --output) the error body first
+    #[arg(long, conflicts_with = "fail")]
+    fail_with_body: bool,
+
+    /// Stop a batch or repeated run at the first failing request
+    #[arg(long)]
+    stop_on_error: bool,
+
+    /// File of default "Key: Value" headers, one per line; --header overrides
+    #[arg(long)]
+    header_file: Option<String>,
+
+    /// Retry up to N times on connection errors and 429/503 responses. Only
+    /// idempotent methods (GET, HEAD, PUT, DELETE, OPTIONS, TRACE) are retried
+    #[arg(long, default_value_t = 0)]
+    retry: u32,
+
+    /// Let --retry resend non-idempotent methods like POST and PATCH too
+    #[arg(long, requires = "retry")]
+    retry_all_methods: bool,
+
+    /// JSON body, validated and sent with JSON Content-Type and Accept
+    /// headers. Use "@path" to read it from a file
+    #[arg(long, conflicts_with_all = ["body", "form", "body_file"])]
+    json: Option<String>,
+
+    /// Print how long the request took to stderr
+    #[arg(long)]
+    timing: bool,
+
+    /// Skip TLS certificate verification (unsafe, for testing only)
+    #[arg(short = 'k', long)]
+    insecure: bool,
+
+    /// User-Agent to send, overriding any User-Agent --header
+    #[arg(long)]
+    user_agent: Option<String>,
+
+    /// Load cookies from this file and save received cookies back to it
+    #[arg(long)]
+    cookie_jar: Option<String>,
+
+    /// Route requests through this proxy URL. Without it, the HTTP_PROXY and
+    /// HTTPS_PROXY environment variables are used when set
+    #[arg(long)]
+    proxy: Option<String>,
+
+    /// Print only the JSON value at this dotted path (e.g., data.items.0.name)
+    #[arg(long)]
+    jq: Option<String>,
+
+    /// Print only the raw response body, without the status line or prefixes
+    #[arg(short, long)]
+    silent: bool,
+
+    /// Abort if the response body grows beyond this many bytes
+    #[arg(long)]
+    max_response_size: Option<u64>,
+
+    /// How to format the response body
+    #[arg(long, value_enum, default_value_t = OutputFormat::Auto)]
+    output_format: OutputFormat,
+
+    /// Print bodies exactly as received, without reformatting JSON, XML, or
+    /// HTML (the same as --output-format raw)
+    #[arg(long, conflicts_with = "output_format")]
+    no_pretty: bool,
+
+    /// Decode the response body as this charset (e.g. iso-8859-1,
+    /// shift_jis) instead of the one declared in Content-Type
+    #[arg(long, value_parser = parse_charset)]
+    charset: Option<&'static Encoding>,
+
+    /// Highlight JSON bodies with ANSI colors
+    #[arg(long, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+
+    /// Send each request this many times, reporting status and timing
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    repeat: u32,
+
+    /// Seconds to wait between repeated requests (default: none for
+    /// --repeat, 1 for --repeat-until)
+    #[arg(long, value_parser = parse_seconds, requires = "repeating")]
+    interval: Option<f64>,
+
+    /// Resend the request until the JSON value at PATH equals VALUE, e.g.
+    /// "job.state=completed", printing the value after each attempt.
+    /// Attempts are --interval seconds apart, 1 by default
+    #[arg(long, value_name = "PATH=VALUE", value_parser = parse_key_value, conflicts_with_all = ["repeat", "wait_for", "paginate"])]
+    repeat_until: Option<(String, String)>,
+
+    /// Give up on --repeat-until after this many attempts
+    #[arg(long, default_value_t = 60, value_parser = clap::value_parser!(u32).range(1..), requires = "repeat_until")]
+    max_attempts: u32,
+
+    /// Run up to N requests from a batch request file at the same time
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    concurrency: u32,
+
+    /// Send at most this many requests per second (e.g., 10, or 0.5 for one
+    /// every two seconds), across --repeat, batches, --concurrency, retries,
+    /// and pages alike. Requests wait for their turn instead of failing
+    #[arg(long, value_name = "N", value_parser = parse_rate)]
+    rate_limit: Option<f64>,
+
+    /// Print the request that would be sent and exit without sending it
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Multipart file upload in the form "name=@path" (can be repeated)
+    #[arg(long, value_parser = parse_file_field, conflicts_with_all = ["body", "form", "body_file", "json"])]
+    file_field: Vec<(String, String)>,
+
+    /// Multipart text field in the form "name=value" (can be repeated)
+    #[arg(long, value_parser = parse_key_value, conflicts_with_all = ["body", "form", "body_file", "json"])]
+    field: Vec<(String, String)>,
+
+    /// Request a compressed response and decompress it (gzip, deflate, br).
+    /// Without it, compressed bodies are left exactly as received
+    #[arg(long)]
+    compressed: bool,
+
+    /// Send the body as a JSON Merge Patch or JSON Patch document, setting
+    /// its Content-Type and checking its shape before sending
+    #[arg(long, value_enum, conflicts_with_all = ["body_file", "form", "field", "file_field", "graphql"])]
+    patch_type: Option<patch::PatchType>,
+
+    /// Gzip the request body and send it with `Content-Encoding: gzip`
+    #[arg(long, conflicts_with_all = ["body_file", "form", "field", "file_field"])]
+    compress_request: bool,
+
+    /// HTTP body sent byte-for-byte, with no special handling of "-"
+    #[arg(long, conflicts_with_all = ["body", "form", "json", "body_file", "file_field", "field"])]
+    data_raw: Option<String>,
+
+    /// Binary HTTP body: "-" reads raw bytes from stdin, "@path" reads a
+    /// file, anything else is sent as-is. No Content-Type is guessed for it
+    #[arg(long, conflicts_with_all = ["body", "form", "json", "body_file", "data_raw", "file_field", "field", "graphql"])]
+    data_binary: Option<String>,
+
+    /// Don't guess a Content-Type (JSON or plain text) for bodies without one
+    #[arg(long)]
+    no_auto_content_type: bool,
+
+    /// Exit with an error unless the response has this status code
+    #[arg(long)]
+    expect_status: Option<u16>,
+
+    /// Exit with an error unless the response body contains this text (can be
+    /// repeated)
+    #[arg(long)]
+    expect_body_contains: Vec<String>,
+
+    /// Exit with an error unless the response body is JSON matching this
+    /// JSON Schema file
+    #[arg(long, conflicts_with_all = ["status_only", "no_body", "head_only"])]
+    schema: Option<String>,
+
+    /// Compare the response body with this fixture file, printing a unified
+    /// diff and exiting with an error if they differ. JSON bodies are
+    /// compared with their keys sorted, so key order doesn't count
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["status_only", "no_body", "head_only"])]
+    diff: Option<String>,
+
+    /// Keep requesting the next page while the response links to one
+    #[arg(long, conflicts_with = "output")]
+    paginate: bool,
+
+    /// Dotted JSON path to the next page URL in the body (e.g., links.next).
+    /// Without it, the `rel="next"` entry of the Link header is used
+    #[arg(long, requires = "paginate")]
+    next_field: Option<String>,
+
+    /// Stop paginating after this many pages
+    #[arg(long, requires = "paginate", value_parser = clap::value_parser!(u32).range(1..))]
+    max_pages: Option<u32>,
+
+    /// Only use HTTP/1.1
+    #[arg(long, conflicts_with = "http2")]
+    http1: bool,
+
+    /// Use HTTP/2 without negotiating it first (prior knowledge)
+    #[arg(long)]
+    http2: bool,
+
+    /// Send header names in Title-Case (`Content-Type`, `X-Api-Key`) over
+    /// HTTP/1.1 for servers that reject lowercase names. Headers keep
+    /// their command-line order, but custom casing like `X-API-KEY` can't
+    /// be reproduced
+    #[arg(long, conflicts_with = "http2")]
+    title_case_headers: bool,
+
+    /// Print only the status and response headers, without downloading the
+    /// body. GET requests are sent as HEAD
+    #[arg(long, conflicts_with_all = ["output", "jq", "paginate", "expect_body_contains"])]
+    head_only: bool,
+
+    /// Log the full request and response, bodies included, to stderr
+    #[arg(long)]
+    trace: bool,
+
+    /// GraphQL query, POSTed as a JSON `{"query": ...}` body. Use
+    /// "@file.graphql" to read it from a file
+    #[arg(long, conflicts_with_all = ["body", "form", "json", "body_file", "data_raw", "file_field", "field", "method"])]
+    graphql: Option<String>,
+
+    /// JSON object of variables for --graphql
+    #[arg(long, requires = "graphql")]
+    variables: Option<String>,
+
+    /// Read the response body no faster than this many bytes per second
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    limit_rate: Option<u64>,
+
+    /// Connect through this Unix domain socket instead of TCP. The URL still
+    /// sets the path and Host header (e.g., http://localhost/containers/json)
+    #[arg(long, conflicts_with = "proxy")]
+    unix_socket: Option<String>,
+
+    /// Don't send "Accept: application/json" when no Accept header is given
+    #[arg(long)]
+    no_default_accept: bool,
+
+    /// Parts of the exchange to print: H request headers, B request body,
+    /// h response headers, b response body (e.g., "hb" or "Hh")
+    #[arg(long, value_parser = parse_print_spec, conflicts_with_all = ["include", "silent", "jq", "head_only"])]
+    print: Option<PrintSpec>,
+
+    /// Print each response as one JSON object per line, with its index, url,
+    /// status, and body
+    #[arg(long, conflicts_with_all = ["print", "include", "jq", "silent", "output", "output_dir", "head_only"])]
+    json_lines: bool,
+
+    /// PEM client certificate for mutual TLS. Without --key, the private key
+    /// is read from the same file
+    #[arg(long)]
+    cert: Option<String>,
+
+    /// PEM (PKCS#8) private key for --cert
+    #[arg(long, requires = "cert")]
+    key: Option<String>,
+
+    /// PKCS#12 bundle holding the client certificate and key
+    #[arg(long, conflicts_with_all = ["cert", "key"])]
+    cert_p12: Option<String>,
+
+    /// Password for --cert-p12
+    #[arg(long, requires = "cert_p12")]
+    cert_password: Option<String>,
+
+    /// Read and discard the response body, printing only the status
+    #[arg(long, conflicts_with_all = ["output", "output_dir", "jq", "print", "json_lines", "head_only", "paginate", "expect_body_contains"])]
+    no_body: bool,
+
+    /// Show upload progress for the request body on stderr. Progress bodies
+    /// are streamed, so they aren't retried
+    #[arg(long)]
+    progress: bool,
+
+    /// Print only the numeric status code (e.g., 200). With --fail, error
+    /// statuses still exit with code 22
+    #[arg(long, conflicts_with_all = ["output", "output_dir", "include", "jq", "print", "json_lines", "head_only", "no_body", "paginate", "expect_body_contains"])]
+    status_only: bool,
+
+    /// Print the response body as a hex dump with an ASCII gutter
+    #[arg(long, conflicts_with_all = ["output_format", "jq", "print", "json_lines", "output", "output_dir", "no_body", "status_only"])]
+    hexdump: bool,
+
+    /// Cache GET responses with an ETag here, revalidating them with
+    /// If-None-Match and printing the stored body on 304 Not Modified
+    #[arg(long)]
+    cache_dir: Option<String>,
+
+    /// Resend the request until it succeeds (a 2xx status, or the
+    /// --expect-status code), e.g. to wait for a service to come up
+    #[arg(long, conflicts_with_all = ["paginate", "repeat"])]
+    wait_for: bool,
+
+    /// Seconds to keep trying with --wait-for before giving up
+    #[arg(long, value_parser = parse_seconds, default_value_t = 30.0, requires = "wait_for")]
+    wait_timeout: f64,
+
+    /// Seconds between --wait-for attempts
+    #[arg(long, value_parser = parse_seconds, default_value_t = 1.0, requires = "wait_for")]
+    wait_interval: f64,
+
+    /// Print each response through a template, e.g. "{status} {time}ms
+    /// {header.Content-Type} {body.data.id}". Placeholders: status, url,
+    /// time, body, body.<path>, header.<name>
+    #[arg(long, conflicts_with_all = ["print", "jq", "json_lines", "include", "silent", "hexdump", "output", "output_dir", "head_only", "no_body", "status_only"])]
+    template: Option<String>,
+
+    /// Send requests for HOST to ADDR instead of looking it up, as
+    /// "host:port:addr" (can be repeated). The Host header and TLS name stay
+    /// unchanged. The port must match the URL's, since the override can't be
+    /// limited to one port of a host
+    #[arg(long, value_parser = parse_resolve)]
+    resolve: Vec<(String, std::net::SocketAddr)>,
+
+    /// Serve GET responses only from --cache-dir, failing for anything
+    /// that isn't cached instead of touching the network
+    #[arg(long, requires = "cache_dir")]
+    offline: bool,
+
+    /// Refuse to send a request body larger than this many bytes
+    #[arg(long)]
+    max_upload_size: Option<u64>,
+
+    /// Don't ask for confirmation before uploading a large body
+    #[arg(short, long)]
+    yes: bool,
+
+    /// Append a JSON line per request (method, URL, status, duration,
+    /// size) to this file
+    #[arg(long)]
+    log_file: Option<String>,
+
+    /// Keep Authorization, Cookie, and other credentials in --log-file
+    /// instead of redacting them
+    #[arg(long, requires = "log_file")]
+    log_secrets: bool,
+
+    /// Write every request and response to this file as a HAR 1.2 log,
+    /// with credentials redacted
+    #[arg(long)]
+    har: Option<String>,
+
+    /// TOML or JSON file of defaults for --timeout, --header, --user-agent,
+    /// --proxy, --follow, --base-url, --bearer, and --user
+    /// (default: ~/.config/fetch/config.toml)
+    #[arg(long)]
+    config: Option<String>,
+
+    /// Named environment from the config file to layer over its defaults
+    /// (e.g., dev, staging, prod)
+    #[arg(long)]
+    env: Option<String>,
+
+    /// Whether --bearer or --user was filled in from the config rather than
+    /// given on the command line
+    #[arg(skip)]
+    auth_from_config: bool,
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let cli = Cli::parse();
-
-    let (url, method, body_opt, headers_vec) = if let Some(file_path) = cli.file {
-        let file_content = std::fs::read_to_string(file_path)?;
-        let parsed: RequestFile = serde_json::from_str(&file_content)?;
-        (
-            parsed.url,
-            parsed.method.to_uppercase(),
-            parsed.body.map(|v| v.to_string()),
-            parsed.headers.unwrap_or_default(),
-        )
-    } else {
-        (
-            cli.host.expect("host is required if file is not provided"),
-            cli.method.to_uppercase(),
-            cli.body,
-            cli.header,
-        )
+/// Looks up a `--charset` label like `latin1` or `utf-8`.
+fn parse_charset(s: &str) -> Result<&'static Encoding, String> {
+    Encoding::for_label(s.as_bytes()).ok_or_else(|| format!("unknown charset: {}", s))
+}
+
+/// Parses a non-negative number of seconds for duration flags.
+fn parse_seconds(s: &str) -> Result<f64, String> {
+    let secs: f64 = s
+        .parse()
+        .map_err(|_| format!("invalid number of seconds: {}", s))?;
+    Duration::try_from_secs_f64(secs).map_err(|_| format!("invalid number of seconds: {}", s))?;
+    Ok(secs)
+}
+
+/// Parses a `--continue-at` byte offset or `-`.
+fn parse_continue_at(s: &str) -> Result<ContinueAt, String> {
+    if s == "-" {
+        return Ok(ContinueAt::Auto);
+    }
+    s.parse()
+        .map(ContinueAt::Offset)
+        .map_err(|_| format!("invalid offset {:?}, expected a byte count or -", s))
+}
+
+/// Parses a positive `--rate-limit` in requests per second.
+fn parse_rate(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(rate) if rate > 0.0 && rate.is_finite() => Ok(rate),
+        _ => Err(format!(
+            "invalid rate {:?}, expected requests per second above 0",
+            s
+        )),
+    }
+}
+
+/// Parses a `--print` spec such as "Hhb".
+fn parse_print_spec(s: &str) -> Result<PrintSpec, String> {
+    if let Some(c) = s.chars().find(|c| !"HBhb".contains(*c)) {
+        return Err(format!(
+            "unknown --print part {:?}, expected H, B, h, or b",
+            c
+        ));
+    }
+    if s.is_empty() {
+        return Err("expected at least one of H, B, h, or b".to_string());
+    }
+    Ok(PrintSpec {
+        request_headers: s.contains('H'),
+        request_body: s.contains('B'),
+        response_headers: s.contains('h'),
+        response_body: s.contains('b'),
+    })
+}
+
+/// Formats headers as `Key: Value` lines, decoding non-UTF-8 values lossily.
+fn format_headers(headers: &HeaderMap) -> String {
+    headers
+        .iter()
+        .map(|(name, value)| format!("{}: {}\n", name, String::from_utf8_lossy(value.as_bytes())))
+        .collect()
+}
+
+/// Parses a "key=value" pair, keeping any further `=` in the value.
+fn parse_key_value(s: &str) -> Result<(String, String), String> {
+    s.split_once('=')
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .ok_or_else(|| format!("expected key=value, got: {}", s))
+}
+
+/// Parses a `--resolve` "host:port:addr" entry; IPv6 addresses may be
+/// bracketed.
+fn parse_resolve(s: &str) -> Result<(String, std::net::SocketAddr), String> {
+    let invalid = || format!("expected host:port:addr, got: {}", s);
+    let mut parts = s.splitn(3, ':');
+    let (Some(host), Some(port), Some(addr)) = (parts.next(), parts.next(), parts.next()) else {
+        return Err(invalid());
+    };
+    if host.is_empty() {
+        return Err(invalid());
+    }
+    let port: u16 = port
+        .parse()
+        .map_err(|_| format!("invalid port {:?} in: {}", port, s))?;
+    let ip: std::net::IpAddr = addr
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse()
+        .map_err(|_| format!("invalid IP address {:?} in: {}", addr, s))?;
+    Ok((host.to_string(), std::net::SocketAddr::new(ip, port)))
+}
+
+/// Fails when a `--resolve` entry for the URL's host names another port.
+///
+/// reqwest overrides a host name on every port, so sending the request
+/// would quietly use an entry meant for a different port.
+fn check_resolve_port(cli: &Cli, url: &reqwest::Url) -> error::Result<()> {
+    let (Some(host), Some(port)) = (url.host_str(), url.port_or_known_default()) else {
+        return Ok(());
     };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    match cli
+        .resolve
+        .iter()
+        .find(|(name, addr)| name.eq_ignore_ascii_case(host) && addr.port() != port)
+    {
+        Some((name, addr)) => Err(FetchError::Usage(format!(
+            "--resolve {}:{} doesn't match port {} in {}; use {}:{}:{}",
+            name,
+            addr.port(),
+            port,
+            url,
+            name,
+            port,
+            addr.ip()
+        ))),
+        None => Ok(()),
+    }
+}
 
-    let client = reqwest::Client::new();
+/// Parses a "name=@path" multipart file field.
+fn parse_file_field(s: &str) -> Result<(String, String), String> {
+    match s.split_once("=@") {
+        Some((name, path)) if !path.is_empty() => Ok((name.to_string(), path.to_string())),
+        _ => Err(format!("expected name=@path, got: {}", s)),
+    }
+}
 
-    let mut headers = HeaderMap::new();
-    for h in headers_vec {
-        if let Some((k, v)) = h.split_once(":") {
-            headers.insert(
-                HeaderName::from_str(k.trim())?,
-                HeaderValue::from_str(v.trim())?,
-            );
+/// Resolves the `--body` argument, reading stdin when it is exactly "-".
+fn resolve_body(body: String) -> error::Result<String> {
+    if body != "-" {
+        return read_at_file(body);
+    }
+
+    // An interactive terminal has nothing piped in, so don't wait on it
+    let stdin = std::io::stdin();
+    if stdin.is_terminal() {
+        return Ok(String::new());
+    }
+
+    std::io::read_to_string(stdin).map_err(|e| FetchError::read("stdin", e))
+}
+
+/// Reads `@path` values from the file, like curl. A leading `\@` stands
+/// for a literal `@`.
+fn read_at_file(value: String) -> error::Result<String> {
+    if let Some(literal) = value.strip_prefix("\\@") {
+        return Ok(format!("@{}", literal));
+    }
+    match value.strip_prefix('@') {
+        Some(path) => std::fs::read_to_string(path).map_err(|e| FetchError::read(path, e)),
+        None => Ok(value),
+    }
+}
+
+/// Reads a `--data-binary` value as raw bytes, from stdin for "-" or from a
+/// file for "@path".
+fn read_binary(data: &str) -> error::Result<Vec<u8>> {
+    use std::io::Read;
+
+    if data == "-" {
+        let mut bytes = Vec::new();
+        std::io::stdin()
+            .read_to_end(&mut bytes)
+            .map_err(|e| FetchError::read("stdin", e))?;
+        return Ok(bytes);
+    }
+    match data.strip_prefix('@') {
+        Some(path) => std::fs::read(path).map_err(|e| FetchError::read(path, e)),
+        None => Ok(data.as_bytes().to_vec()),
+    }
+}
+
+/// Formats the outgoing request curl-style, hiding sensitive header values.
+fn format_request(request: &reqwest::Request) -> String {
+    let mut text = format!("> {} {}\n", request.method(), request.url());
+    for (name, value) in request.headers() {
+        if value.is_sensitive() {
+            text.push_str(&format!("> {}: <redacted>\n", name));
         } else {
-            eprintln!("Invalid header format: {}", h);
-            return Ok(());
+            text.push_str(&format!(
+                "> {}: {}\n",
+                name,
+                String::from_utf8_lossy(value.as_bytes())
+            ));
         }
     }
+    text.push_str(">\n");
+    if let Some(body) = request_body_text(request) {
+        text.push_str(&format!("{}\n", body));
+    }
+    text
+}
 
-    let request_builder = match method.as_str() {
-        "GET" => client.get(&url),
-        "POST" => client.post(&url),
-        "PUT" => client.put(&url),
-        "DELETE" => client.delete(&url),
-        _ => {
-            eprintln!("Unsupported method: {}", method);
-            return Ok(());
-        }
+/// The request body as text for display, or a summary like `<25 bytes,
+/// gzip>` when it is encoded (e.g. by `--compress-request`).
+fn request_body_text(request: &reqwest::Request) -> Option<String> {
+    let bytes = request
+        .body()
+        .and_then(|b| b.as_bytes())
+        .filter(|bytes| !bytes.is_empty())?;
+    match request
+        .headers()
+        .get(CONTENT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+    {
+        Some(encoding) => Some(format!("<{} bytes, {}>", bytes.len(), encoding)),
+        None => Some(String::from_utf8_lossy(bytes).into_owned()),
+    }
+}
+
+/// Parses a "Key: Value" header, naming the offending header on failure.
+fn parse_header(header: &str) -> error::Result<(HeaderName, HeaderValue)> {
+    let invalid = |reason: String| FetchError::InvalidHeader {
+        header: header.to_string(),
+        reason,
+    };
+    let Some((k, v)) = header.split_once(':') else {
+        return Err(invalid("expected \"Key: Value\"".to_string()));
     };
+    let name = HeaderName::from_str(k.trim()).map_err(|e| invalid(e.to_string()))?;
+    let value = HeaderValue::from_str(v.trim()).map_err(|e| invalid(e.to_string()))?;
+    Ok((name, value))
+}
+
+/// Whether the `Name: value` line `header` sets the header `name`.
+fn same_header(header: &str, name: &str) -> bool {
+    header
+        .split_once(':')
+        .is_some_and(|(n, _)| n.trim().eq_ignore_ascii_case(name))
+}
 
-    let request = request_builder
-        .headers(headers)
-        .body(body_opt.unwrap_or_default())
-        .build()?;
+/// Puts `overrides` after `base`, dropping any `base` header they set again.
+///
+/// Repeats within one layer are kept, so they are all sent.
+fn layer_headers(base: Vec<String>, overrides: Vec<String>) -> Vec<String> {
+    let mut headers: Vec<String> = base
+        .into_iter()
+        .filter(|header| {
+            let name = header.split_once(':').map_or("", |(name, _)| name.trim());
+            !overrides.iter().any(|o| same_header(o, name))
+        })
+        .collect();
+    headers.extend(overrides);
+    headers
+}
 
-    let response = client.execute(request).await?;
-    let status = response.status();
-    let body = response.text().await?;
+/// Reads the `--header-from-env` variables into "Name: value" headers.
+///
+/// Fails listing every variable that isn't set, rather than sending the
+/// headers empty.
+fn env_headers(cli: &Cli) -> error::Result<Vec<String>> {
+    let mut headers = Vec::new();
+    let mut missing = Vec::new();
+    for (name, variable) in &cli.header_from_env {
+        match std::env::var(variable) {
+            Ok(value) => headers.push(format!("{}: {}", name, value)),
+            Err(_) => missing.push(variable.as_str()),
+        }
+    }
+    if missing.is_empty() {
+        Ok(headers)
+    } else {
+        Err(FetchError::Usage(format!(
+            "Undefined environment variable(s) for --header-from-env: {}",
+            missing.join(", ")
+        )))
+    }
+}
+
+/// Reads the lines of a file, skipping blank lines and `#` comments.
+fn read_lines(path: &str) -> error::Result<Vec<String>> {
+    let content = std::fs::read_to_string(path).map_err(|e| FetchError::read(path, e))?;
+    Ok(content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect())
+}
+
+/// Builds the JSON envelope for `--graphql`, reading `@path` queries from disk.
+fn graphql_body(query: &str, variables: Option<&str>) -> error::Result<Vec<u8>> {
+    let query = match query.strip_prefix('@') {
+        Some(path) => std::fs::read_to_string(path).map_err(|e| FetchError::read(path, e))?,
+        None => query.to_string(),
+    };
+    let mut envelope = serde_json::json!({ "query": query });
+    if let Some(variables) = variables {
+        envelope["variables"] = serde_json::from_str(variables)
+            .map_err(|e| FetchError::Parse(format!("Invalid JSON in --variables: {}", e)))?;
+    }
+    Ok(envelope.to_string().into_bytes())
+}
+
+/// Reports the top-level `errors` of a GraphQL response, failing if any.
+fn check_graphql_errors(body: &str, printer: &mut Printer) -> error::Result<()> {
+    let Ok(json) = serde_json::from_str::<serde_json::Value>(body) else {
+        return Ok(());
+    };
+    let Some(errors) = json.get("errors").and_then(|e| e.as_array()) else {
+        return Ok(());
+    };
+    if errors.is_empty() {
+        return Ok(());
+    }
+    for error in errors {
+        let message = error
+            .get("message")
+            .and_then(|m| m.as_str())
+            .map(String::from)
+            .unwrap_or_else(|| error.to_string());
+        errln!(printer, "GraphQL error: {}", message);
+    }
+    Err(FetchError::Response(format!(
+        "GraphQL response contained {} error(s)",
+        errors.len()
+    )))
+}
 
-    println!("Status: {}", status);
+/// Builds the request described by the CLI flags.
+fn cli_request(cli: &Cli) -> error::Result<RequestSpec> {
+    let mut headers = Vec::new();
 
-    if let Ok(json) = serde_json::from_str::<serde_json::Value>(&body) {
-        let pretty = serde_json::to_string_pretty(&json)?;
-        println!("Body:\n{}", pretty);
+    let body = if let Some(query) = &cli.graphql {
+        headers.push("Content-Type: application/json".to_string());
+        headers.push("Accept: application/json".to_string());
+        Some(graphql_body(query, cli.variables.as_deref())?)
+    } else if let Some(json) = &cli.json {
+        let json = &read_at_file(json.clone())?;
+        if let Err(e) = serde_json::from_str::<serde_json::Value>(json) {
+            return Err(FetchError::Parse(format!("Invalid JSON in --json: {}", e)));
+        }
+        // Listed before --header entries so those can still override them
+        headers.push("Content-Type: application/json".to_string());
+        headers.push("Accept: application/json".to_string());
+        Some(json.clone().into_bytes())
+    } else if let Some(raw) = &cli.data_raw {
+        Some(raw.clone().into_bytes())
+    } else if let Some(data) = &cli.data_binary {
+        Some(read_binary(data)?)
+    } else if cli.body_file.is_some() {
+        // Streamed from disk when the request is sent
+        None
     } else {
-        println!("Body:\n{}", body);
+        cli.body
+            .clone()
+            .map(resolve_body)
+            .transpose()?
+            .map(String::into_bytes)
+    };
+    // A patch is JSON of a more specific type, so it replaces the plain JSON
+    // type from --json or --graphql; --header still overrides both
+    if let Some(patch_type) = cli.patch_type {
+        headers.retain(|header| !same_header(header, "Content-Type"));
+        headers.push(format!("Content-Type: {}", patch_type.content_type()));
     }
 
-    Ok(())
+    Ok(RequestSpec {
+        name: None,
+        // Empty only for --hosts-file, which fills in each URL itself
+        url: cli.host.clone().unwrap_or_default(),
+        method: if cli.graphql.is_some() {
+            "POST".to_string()
+        } else {
+            cli.method.to_uppercase()
+        },
+        body,
+        headers: layer_headers(headers, cli.header.clone()),
+        query: Vec::new(),
+        output: None,
+        body_file: cli.body_file.clone(),
+    })
+}
+
+/// Joins a relative URL onto `--base-url`. Absolute URLs are kept as they are.
+fn resolve_url(base: Option<&reqwest::Url>, url: String) -> error::Result<String> {
+    let Some(base) = base else {
+        return Ok(url);
+    };
+    if reqwest::Url::parse(&url).is_ok() {
+        eprintln!("Warning: {} is absolute, ignoring --base-url", url);
+        return Ok(url);
+    }
+    base.join(&url)
+        .map(String::from)
+        .map_err(|e| FetchError::Usage(format!("Cannot join {} onto --base-url: {}", url, e)))
+}
+
+/// Turns a request name into something safe to use in a file name.
+fn file_name_slug(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect::<String>()
+        .split('-')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Loads the cookie jar file, starting empty when it doesn't exist yet.
+fn load_cookie_jar(path: &str) -> error::Result<CookieStore> {
+    let content = match std::fs::read(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(CookieStore::default()),
+        Err(e) => return Err(FetchError::read(path, e)),
+    };
+    cookie_store::serde::json::load_all(content.as_slice())
+        .or_else(|e| {
+            // Older versions of fetch wrote one cookie per line instead of an array
+            #[allow(deprecated)]
+            CookieStore::load_json_all(content.as_slice()).map_err(|_| e)
+        })
+        .map_err(|e| FetchError::Parse(format!("Invalid cookie jar {}: {}", path, e)))
+}
+
+/// Writes every cookie, including session cookies, back to the jar file.
+fn save_cookie_jar(path: &str, jar: &CookieStoreMutex) -> error::Result<()> {
+    let mut file = std::fs::File::create(path).map_err(|e| FetchError::write(path, e))?;
+    cookie_store::serde::json::save_incl_expired_and_nonpersistent(&jar.lock().unwrap(), &mut file)
+        .map_err(|e| FetchError::write(path, std::io::Error::other(e.to_string())))
+}
+
+/// Loads the client certificate from `--cert`/`--key` or `--cert-p12`.
+fn load_identity(cli: &Cli) -> error::Result<Option<reqwest::Identity>> {
+    let read = |path: &str| std::fs::read(path).map_err(|e| FetchError::read(path, e));
+    let invalid = |path: &str, e: reqwest::Error| {
+        // reqwest only says "builder error"; the TLS backend says why
+        let reason = std::error::Error::source(&e).map_or_else(|| e.to_string(), |s| s.to_string());
+        FetchError::Parse(format!("Invalid client certificate {}: {}", path, reason))
+    };
+
+    if let Some(path) = &cli.cert_p12 {
+        let der = read(path)?;
+        let password = cli.cert_password.as_deref().unwrap_or_default();
+        return reqwest::Identity::from_pkcs12_der(&der, password)
+            .map(Some)
+            .map_err(|e| invalid(path, e));
+    }
+    let Some(cert_path) = &cli.cert else {
+        return Ok(None);
+    };
+    let cert = read(cert_path)?;
+    let key = match &cli.key {
+        Some(key_path) => read(key_path)?,
+        None => cert.clone(),
+    };
+    reqwest::Identity::from_pkcs8_pem(&cert, &key)
+        .map(Some)
+        .map_err(|e| invalid(cert_path, e))
+}
+
+/// Builds the shared HTTP client from the CLI flags.
+fn build_client(
+    cli: &Cli,
+    cookie_jar: Option<Arc<CookieStoreMutex>>,
+) -> error::Result<reqwest::Client> {
+    let mut client_builder = reqwest::Client::builder();
+    if let Some(proxy_url) = &cli.proxy {
+        let proxy = reqwest::Proxy::all(proxy_url)
+            .map_err(|e| FetchError::Usage(format!("Invalid proxy URL {}: {}", proxy_url, e)))?;
+        client_builder = client_builder.proxy(proxy);
+    }
+    if let Some(path) = &cli.unix_socket {
+        #[cfg(unix)]
+        {
+            client_builder = client_builder.unix_socket(std::path::Path::new(path));
+        }
+        #[cfg(not(unix))]
+        return Err(FetchError::Usage(format!(
+            "--unix-socket {} is only supported on Unix",
+            path
+        )));
+    }
+    for (i, (host, addr)) in cli.resolve.iter().enumerate() {
+        // A later entry for the same host would replace this one, whatever its port
+        if cli.resolve[..i]
+            .iter()
+            .any(|(earlier, _)| earlier.eq_ignore_ascii_case(host))
+        {
+            return Err(FetchError::Usage(format!(
+                "--resolve can only give one address for {}",
+                host
+            )));
+        }
+        client_builder = client_builder.resolve(host, *addr);
+    }
+    if let Some(jar) = cookie_jar {
+        client_builder = client_builder.cookie_provider(jar);
+    }
+    if let Some(secs) = cli.timeout {
+        client_builder = client_builder.timeout(Duration::from_secs_f64(secs));
+    }
+    if let Some(secs) = cli.connect_timeout {
+        client_builder = client_builder.connect_timeout(Duration::from_secs_f64(secs));
+    }
+    if let Some(identity) = load_identity(cli)? {
+        client_builder = client_builder.identity(identity);
+    }
+    if cli.insecure {
+        eprintln!("WARNING: TLS certificate verification is disabled (--insecure)");
+        client_builder = client_builder.danger_accept_invalid_certs(true);
+    }
+    client_builder = client_builder
+        .gzip(cli.compressed)
+        .deflate(cli.compressed)
+        .brotli(cli.compressed);
+    if cli.title_case_headers {
+        client_builder = client_builder.http1_only().http1_title_case_headers();
+    } else if cli.http1 {
+        client_builder = client_builder.http1_only();
+    } else if cli.http2 {
+        client_builder = client_builder.http2_prior_knowledge();
+    }
+    client_builder = client_builder.redirect(if cli.follow {
+        Policy::limited(cli.max_redirects)
+    } else {
+        Policy::none()
+    });
+    Ok(client_builder.build()?)
+}
+
+/// Reads a `Retry-After` header given in seconds.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let secs = headers
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()?;
+    Some(Duration::from_secs(secs))
+}
+
+/// Whether resending `method` can't repeat a side effect (RFC 9110 9.2.2).
+fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::PUT | Method::DELETE | Method::OPTIONS | Method::TRACE
+    )
+}
+
+/// Randomizes a backoff to between half and all of its length, so clients
+/// that failed together don't all retry at the same moment.
+fn jitter(backoff: Duration) -> Duration {
+    let random = RandomState::new().hash_one(Instant::now());
+    let fraction = (random % 1000) as f64 / 1000.0;
+    backoff.mul_f64(0.5 + fraction / 2.0)
+}
+
+/// Executes a request, retrying transient failures as configured by `--retry`.
+///
+/// Connection errors back off exponentially from 100ms with jitter, while
+/// 429 and 503 responses wait for their `Retry-After` header when the server
+/// sends one. Non-idempotent methods are only retried with
+/// `--retry-all-methods`, so a POST is never submitted twice by accident.
+async fn execute_with_retry(
+    cli: &Cli,
+    client: &reqwest::Client,
+    mut request: reqwest::Request,
+    printer: &mut Printer,
+) -> reqwest::Result<reqwest::Response> {
+    let retries = if cli.retry_all_methods || is_idempotent(request.method()) {
+        cli.retry
+    } else {
+        0
+    };
+    let mut attempt = 0;
+    loop {
+        // Streaming bodies can't be cloned, so those requests are sent only once
+        let next_request = if attempt < retries {
+            request.try_clone()
+        } else {
+            None
+        };
+        let result = client.execute(request).await;
+        let Some(next_request) = next_request else {
+            return result;
+        };
+
+        let backoff = jitter(Duration::from_millis(100 * 2u64.pow(attempt)));
+        let delay = match &result {
+            Ok(response)
+                if matches!(
+                    response.status(),
+                    StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE
+                ) =>
+            {
+                errln!(printer, "Received {}", response.status());
+                retry_after(response.headers()).unwrap_or(backoff)
+            }
+            // is_request covers connections reset while the request was sent
+            Err(e) if e.is_connect() || e.is_timeout() || e.is_request() => {
+                errln!(printer, "Request failed: {}", e);
+                backoff
+            }
+            _ => return result,
+        };
+
+        attempt += 1;
+        errln!(
+            printer,
+            "Retrying in {}ms (attempt {}/{})",
+            delay.as_millis(),
+            attempt,
+            cli.retry
+        );
+        tokio::time::sleep(delay).await;
+        rate_limit::acquire().await;
+        request = next_request;
+    }
+}
+
+/// Sends a request with retries, answering a Digest challenge when
+/// `--digest` is set.
+async fn send_request(
+    cli: &Cli,
+    client: &reqwest::Client,
+    request: reqwest::Request,
+    printer: &mut Printer,
+) -> error::Result<reqwest::Response> {
+    let timed_out = |e: reqwest::Error| match cli.connect_timeout {
+        Some(secs) if e.is_timeout() && e.is_connect() => FetchError::ConnectTimeout(secs),
+        _ if e.is_timeout() => FetchError::Timeout(cli.timeout.unwrap_or_default()),
+        _ => e.into(),
+    };
+
+    let Some(credentials) = &cli.digest else {
+        return execute_with_retry(cli, client, request, printer)
+            .await
+            .map_err(timed_out);
+    };
+    let authenticated = request.try_clone();
+    let response = execute_with_retry(cli, client, request, printer)
+        .await
+        .map_err(timed_out)?;
+    if response.status() != StatusCode::UNAUTHORIZED {
+        return Ok(response);
+    }
+
+    let challenge = response
+        .headers()
+        .get_all(WWW_AUTHENTICATE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .find_map(digest::Challenge::parse);
+    let (Some(mut authenticated), Some(challenge)) = (authenticated, challenge) else {
+        errln!(
+            printer,
+            "Warning: no usable Digest challenge in the 401 response"
+        );
+        return Ok(response);
+    };
+
+    let (username, password) = credentials
+        .split_once(':')
+        .unwrap_or((credentials.as_str(), ""));
+    let url = authenticated.url();
+    let uri = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    };
+    let authorization =
+        challenge.authorization(username, password, authenticated.method().as_str(), &uri);
+    let mut value = HeaderValue::from_str(&authorization)
+        .map_err(|e| FetchError::Usage(format!("Invalid --digest credentials: {}", e)))?;
+    value.set_sensitive(true);
+    authenticated.headers_mut().insert(AUTHORIZATION, value);
+
+    if cli.verbose || cli.trace {
+        errln!(printer, "< {:?} {}", response.version(), response.status());
+        err!(printer, "{}", format_request(&authenticated));
+    }
+    rate_limit::acquire().await;
+    execute_with_retry(cli, client, authenticated, printer)
+        .await
+        .map_err(timed_out)
+}
+
+/// Looks up a dotted path in a JSON value, using numeric segments as array
+/// indexes. Returns the first segment that couldn't be resolved on failure.
+fn json_path<'a>(
+    value: &'a serde_json::Value,
+    path: &'a str,
+) -> Result<&'a serde_json::Value, &'a str> {
+    if path.is_empty() {
+        return Ok(value);
+    }
+    path.split('.').try_fold(value, |current, segment| {
+        let next = match current {
+            serde_json::Value::Array(items) => {
+                segment.parse::<usize>().ok().and_then(|i| items.get(i))
+            }
+            serde_json::Value::Object(map) => map.get(segment),
+            _ => None,
+        };
+        next.ok_or(segment)
+    })
+}
+
+/// Formats an extracted JSON value: scalars raw, objects and arrays pretty.
+fn format_json_value(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s.clone(),
+        serde_json::Value::Array(_) | serde_json::Value::Object(_) => {
+            serde_json::to_string_pretty(value).unwrap_or_default()
+        }
+        other => other.to_string(),
+    }
+}
+
+/// Decides whether stdout output should be colored.
+fn use_color(choice: ColorChoice) -> bool {
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => {
+            std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+        }
+    }
+}
+
+/// Formats a body for display: XML and HTML by their Content-Type in the
+/// default `auto` format, everything else as `format_body` does.
+fn format_response(cli: &Cli, headers: &HeaderMap, body: &str) -> error::Result<String> {
+    let markup = headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(markup::Kind::from_content_type);
+    match markup {
+        // Bodies that don't parse are shown as they came
+        Some(kind) if cli.output_format == OutputFormat::Auto => {
+            Ok(markup::pretty(body, kind).unwrap_or_else(|| body.to_string()))
+        }
+        _ => format_body(cli.output_format, body, use_color(cli.color)),
+    }
+}
+
+/// Formats a response body according to `--output-format`, highlighting
+/// reformatted JSON when `color` is set.
+fn format_body(format: OutputFormat, body: &str, color: bool) -> error::Result<String> {
+    let parsed = serde_json::from_str::<serde_json::Value>(body);
+    match (format, parsed) {
+        (OutputFormat::Raw, _) | (OutputFormat::Auto, Err(_)) => Ok(body.to_string()),
+        (OutputFormat::Auto | OutputFormat::Pretty, Ok(json)) if color => {
+            Ok(color::colorize_json(&json, true))
+        }
+        (OutputFormat::Auto | OutputFormat::Pretty, Ok(json)) => {
+            Ok(serde_json::to_string_pretty(&json).unwrap_or_default())
+        }
+        (OutputFormat::Compact, Ok(json)) if color => Ok(color::colorize_json(&json, false)),
+        (OutputFormat::Compact, Ok(json)) => Ok(serde_json::to_string(&json).unwrap_or_default()),
+        (OutputFormat::Pretty | OutputFormat::Compact, Err(e)) => Err(FetchError::Response(
+            format!("Response body is not JSON: {}", e),
+        )),
+    }
+}
+
+/// Reports how long the request took when `--timing` or `--repeat` is set.
+fn print_timing(cli: &Cli, elapsed: Duration, printer: &mut Printer) {
+    if cli.timing || cli.repeat > 1 {
+        errln!(printer, "Time: {}ms", elapsed.as_millis());
+    }
+}
+
+/// Reads the whole response body, enforcing `--max-response-size` and
+/// pacing reads to `--limit-rate` if set. Each chunk also goes to
+/// `download` as it arrives.
+async fn read_body(
+    cli: &Cli,
+    mut response: reqwest::Response,
+    mut download: Option<&mut download::Download>,
+) -> error::Result<Vec<u8>> {
+    if cli.max_response_size.is_none() && cli.limit_rate.is_none() && download.is_none() {
+        return Ok(response
+            .bytes()
+            .await
+            .map_err(|e| body_error(cli, e))?
+            .to_vec());
+    }
+
+    let start = Instant::now();
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(|e| body_error(cli, e))? {
+        if let Some(limit) = cli.max_response_size
+            && (body.len() + chunk.len()) as u64 > limit
+        {
+            return Err(FetchError::Response(format!(
+                "Response body exceeds the {} byte limit",
+                limit
+            )));
+        }
+        if let Some(download) = download.as_mut() {
+            download.write(&chunk)?;
+        }
+        body.extend_from_slice(&chunk);
+
+        // Sleep until the average rate so far is back under the limit
+        if let Some(rate) = cli.limit_rate {
+            let due = Duration::from_secs_f64(body.len() as f64 / rate as f64);
+            if let Some(ahead) = due.checked_sub(start.elapsed()) {
+                tokio::time::sleep(ahead).await;
+            }
+        }
+    }
+    Ok(body)
+}
+
+/// Reports `--timeout` running out while the body is still arriving as a
+/// timeout rather than a network error.
+fn body_error(cli: &Cli, e: reqwest::Error) -> FetchError {
+    if e.is_timeout() {
+        FetchError::Timeout(cli.timeout.unwrap_or_default())
+    } else {
+        e.into()
+    }
+}
+
+/// Parses a `Content-Length` header.
+fn content_length(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get(CONTENT_LENGTH)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Reports how many body bytes arrived for `--verbose`, warning when that
+/// disagrees with the declared `Content-Length`, which usually means the
+/// transfer was cut short.
+fn report_transfer(cli: &Cli, headers: &HeaderMap, received: usize, printer: &mut Printer) {
+    if !cli.verbose && !cli.trace {
+        return;
+    }
+    match content_length(headers) {
+        Some(declared) if declared != received as u64 => {
+            errln!(
+                printer,
+                "* Received {} bytes (Content-Length: {})",
+                received,
+                declared
+            );
+            errln!(
+                printer,
+                "Warning: received {} bytes but Content-Length declared {}; the response may be truncated",
+                received,
+                declared
+            );
+        }
+        Some(declared) => errln!(
+            printer,
+            "* Received {} bytes (Content-Length: {})",
+            received,
+            declared
+        ),
+        None => errln!(printer, "* Received {} bytes (no Content-Length)", received),
+    }
+}
+
+/// Logs the response body for `--trace`.
+fn trace_body(cli: &Cli, body: &[u8], printer: &mut Printer) {
+    if cli.trace && !body.is_empty() {
+        errln!(printer, "{}", String::from_utf8_lossy(body));
+    }
+}
+
+/// The byte offset `--continue-at` resumes `path` from, if any.
+fn resume_offset(cli: &Cli, path: &str) -> error::Result<Option<u64>> {
+    let offset = match cli.continue_at {
+        None => return Ok(None),
+        Some(ContinueAt::Offset(offset)) => {
+            // Appending at any other point would corrupt the file
+            let existing = download::existing_len(path)?;
+            if offset != existing {
+                return Err(FetchError::Usage(format!(
+                    "--continue-at {} doesn't match the {} bytes already downloaded to {}; use --continue-at -",
+                    offset, existing, path
+                )));
+            }
+            offset
+        }
+        Some(ContinueAt::Auto) => download::existing_len(path)?,
+    };
+    // Nothing to resume, so download the whole file as usual
+    Ok(Some(offset).filter(|offset| *offset > 0))
+}
+
+/// Where the body of a 206 response starts, from `Content-Range: bytes
+/// <start>-<end>/<total>`.
+fn range_start(headers: &HeaderMap) -> Option<u64> {
+    let range = headers.get(CONTENT_RANGE)?.to_str().ok()?;
+    let (start, _) = range.trim().strip_prefix("bytes ")?.split_once('-')?;
+    start.trim().parse().ok()
+}
+
+/// Appends a response's status line and headers to `--output-headers`,
+/// CRLF-terminated like on the wire.
+fn write_headers(
+    path: &str,
+    version: reqwest::Version,
+    status: StatusCode,
+    headers: &HeaderMap,
+) -> error::Result<()> {
+    let mut block = format!("{:?} {}\r\n", version, status);
+    for (name, value) in headers {
+        block.push_str(&format!(
+            "{}: {}\r\n",
+            name,
+            String::from_utf8_lossy(value.as_bytes())
+        ));
+    }
+    block.push_str("\r\n");
+
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| FetchError::write(path, e))?;
+    file.write_all(block.as_bytes())
+        .map_err(|e| FetchError::write(path, e))
+}
+
+/// Gzips a request body for `--compress-request`.
+fn gzip(body: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(body)
+        .and_then(|_| encoder.finish())
+        .expect("writing to a Vec can't fail")
+}
+
+/// Bodies above this size need confirmation in a terminal unless `--yes`
+const CONFIRM_UPLOAD_SIZE: u64 = 10 * 1024 * 1024;
+
+/// Enforces `--max-upload-size`.
+fn check_upload_size(cli: &Cli, size: u64) -> error::Result<()> {
+    match cli.max_upload_size {
+        Some(limit) if size > limit => Err(FetchError::Usage(format!(
+            "Request body is {} bytes, over the --max-upload-size limit of {}",
+            size, limit
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Asks before sending a large body from an interactive terminal, once per
+/// request rather than again for each repeat, attempt, or page.
+fn confirm_upload(cli: &Cli, spec: &RequestSpec) -> error::Result<()> {
+    let size = match (&spec.body, &spec.body_file) {
+        (Some(body), _) => body.len() as u64,
+        // A file that can't be read fails when it is sent instead
+        (None, Some(path)) => match std::fs::metadata(path) {
+            Ok(metadata) => metadata.len(),
+            Err(_) => return Ok(()),
+        },
+        (None, None) => return Ok(()),
+    };
+    let stdin = std::io::stdin();
+    if size <= CONFIRM_UPLOAD_SIZE || cli.yes || cli.dry_run || !stdin.is_terminal() {
+        return Ok(());
+    }
+    eprint!("Upload {} bytes to {}? [y/N] ", size, spec.url);
+    let mut answer = String::new();
+    stdin
+        .read_line(&mut answer)
+        .map_err(|e| FetchError::read("stdin", e))?;
+    if matches!(answer.trim(), "y" | "Y" | "yes") {
+        Ok(())
+    } else {
+        Err(FetchError::Usage("Upload cancelled".to_string()))
+    }
+}
+
+/// Chunk size for streamed request bodies
+const UPLOAD_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Reports upload progress for `--progress`.
+///
+/// Progress is written straight to the terminal rather than through a
+/// `Printer`, since it only makes sense while the upload is running.
+fn report_progress(sent: u64, total: u64) {
+    eprint!(
+        "\rUploaded {}/{} bytes ({}%)",
+        sent,
+        total,
+        sent * 100 / total.max(1)
+    );
+    if sent == total {
+        eprintln!();
+    }
+}
+
+/// Wraps a request body in a stream that reports upload progress.
+fn progress_body(body: Vec<u8>) -> reqwest::Body {
+    let total = body.len() as u64;
+    let chunks: Vec<Vec<u8>> = body.chunks(UPLOAD_CHUNK_SIZE).map(<[u8]>::to_vec).collect();
+    let mut sent = 0;
+    let stream = futures_util::stream::iter(chunks.into_iter().map(move |chunk| {
+        sent += chunk.len() as u64;
+        report_progress(sent, total);
+        Ok::<_, std::io::Error>(chunk)
+    }));
+    reqwest::Body::wrap_stream(stream)
+}
+
+/// Opens `path` as a streamed request body, returning it with its length.
+async fn file_body(path: &str, progress: bool) -> error::Result<(reqwest::Body, u64)> {
+    use tokio::io::AsyncReadExt;
+
+    let file = tokio::fs::File::open(path)
+        .await
+        .map_err(|e| FetchError::read(path, e))?;
+    let total = file
+        .metadata()
+        .await
+        .map_err(|e| FetchError::read(path, e))?
+        .len();
+
+    let stream = futures_util::stream::unfold((file, 0), move |(mut file, sent)| async move {
+        let mut chunk = vec![0; UPLOAD_CHUNK_SIZE];
+        match file.read(&mut chunk).await {
+            Ok(0) => None,
+            Ok(n) => {
+                chunk.truncate(n);
+                let sent = sent + n as u64;
+                if progress {
+                    report_progress(sent, total);
+                }
+                Some((Ok(chunk), (file, sent)))
+            }
+            Err(e) => Some((Err(e), (file, sent))),
+        }
+    });
+    Ok((reqwest::Body::wrap_stream(stream), total))
+}
+
+/// Builds the multipart form from `--field` and `--file-field` flags.
+async fn build_multipart(cli: &Cli) -> error::Result<reqwest::multipart::Form> {
+    let mut form = reqwest::multipart::Form::new();
+    for (name, value) in &cli.field {
+        form = form.text(name.clone(), value.clone());
+    }
+    for (name, path) in &cli.file_field {
+        let bytes = tokio::fs::read(path)
+            .await
+            .map_err(|e| FetchError::read(path, e))?;
+        let mut part = reqwest::multipart::Part::bytes(bytes);
+        if let Some(file_name) = std::path::Path::new(path).file_name() {
+            part = part.file_name(file_name.to_string_lossy().into_owned());
+        }
+        form = form.part(name.clone(), part);
+    }
+    Ok(form)
+}
+
+/// Reads and compiles the JSON Schema at `path`.
+fn load_schema(path: &str) -> error::Result<jsonschema::Validator> {
+    let content = std::fs::read_to_string(path).map_err(|e| FetchError::read(path, e))?;
+    let schema: serde_json::Value = serde_json::from_str(&content)
+        .map_err(|e| FetchError::Parse(format!("Invalid JSON in schema {}: {}", path, e)))?;
+    jsonschema::validator_for(&schema)
+        .map_err(|e| FetchError::Parse(format!("Invalid JSON Schema {}: {}", path, e)))
+}
+
+/// Diffs `body` against the `--diff` fixture at `path`, or `None` if they
+/// match.
+fn fixture_diff(path: &str, body: &str) -> error::Result<Option<String>> {
+    let fixture = std::fs::read_to_string(path).map_err(|e| FetchError::read(path, e))?;
+    let (expected, actual) = match (
+        serde_json::from_str::<serde_json::Value>(&fixture),
+        serde_json::from_str::<serde_json::Value>(body),
+    ) {
+        // Maps are sorted, so re-serializing puts keys in the same order
+        (Ok(expected), Ok(actual)) => (
+            serde_json::to_string_pretty(&expected).expect("JSON value serializes"),
+            serde_json::to_string_pretty(&actual).expect("JSON value serializes"),
+        ),
+        // A trailing newline saved with the fixture isn't a difference
+        _ => (
+            fixture.trim_end_matches('\n').to_string(),
+            body.trim_end_matches('\n').to_string(),
+        ),
+    };
+    if expected == actual {
+        return Ok(None);
+    }
+    let expected = format!("{}\n", expected);
+    let actual = format!("{}\n", actual);
+    let diff = similar::TextDiff::from_lines(&expected, &actual)
+        .unified_diff()
+        .header(path, "response")
+        .to_string();
+    Ok(Some(diff))
+}
+
+/// Checks the response against every `--expect-*` flag, `--schema`, and
+/// `--diff`, reporting all failures together.
+fn check_expectations(cli: &Cli, status: StatusCode, body: &str) -> error::Result<()> {
+    let mut failures = Vec::new();
+    if let Some(expected) = cli.expect_status {
+        if status.as_u16() != expected {
+            failures.push(format!("expected status {}, got {}", expected, status));
+        }
+    } else if cli.wait_for && !status.is_success() {
+        failures.push(format!("expected a 2xx status, got {}", status));
+    }
+    for needle in &cli.expect_body_contains {
+        if !body.contains(needle.as_str()) {
+            failures.push(format!("expected body to contain {:?}", needle));
+        }
+    }
+    if let Some(path) = &cli.schema {
+        let validator = load_schema(path)?;
+        match serde_json::from_str::<serde_json::Value>(body) {
+            Ok(json) => failures.extend(validator.iter_errors(&json).map(|e| {
+                let at = e.instance_path().to_string();
+                format!(
+                    "schema violation at {}: {}",
+                    if at.is_empty() { "/" } else { &at },
+                    e
+                )
+            })),
+            Err(e) => failures.push(format!("expected a JSON body for --schema: {}", e)),
+        }
+    }
+    if let Some(path) = &cli.diff
+        && let Some(diff) = fixture_diff(path, body)?
+    {
+        failures.push(format!("body differs from {}:\n{}", path, diff.trim_end()));
+    }
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(FetchError::Expectation(failures))
+    }
+}
+
+/// Finds the URL of the next page, from `--next-field` in the body or from
+/// the `rel="next"` Link header. Relative links resolve against `url`.
+fn next_page_url(
+    cli: &Cli,
+    url: &reqwest::Url,
+    headers: &HeaderMap,
+    body: &str,
+) -> error::Result<Option<String>> {
+    let next = if let Some(path) = &cli.next_field {
+        let json: serde_json::Value = serde_json::from_str(body)
+            .map_err(|e| FetchError::Response(format!("Response body is not JSON: {}", e)))?;
+        match json_path(&json, path) {
+            Ok(serde_json::Value::String(next)) => Some(next.clone()),
+            Ok(serde_json::Value::Null) | Err(_) => None,
+            Ok(other) => {
+                return Err(FetchError::Response(format!(
+                    "Next page field {} is not a URL: {}",
+                    path, other
+                )));
+            }
+        }
+    } else {
+        headers
+            .get_all(reqwest::header::LINK)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .find_map(|link| {
+                let (target, params) = link.trim().split_once(';')?;
+                let is_next = params
+                    .split(';')
+                    .any(|param| param.trim().replace('"', "") == "rel=next");
+                is_next.then(|| {
+                    target
+                        .trim()
+                        .trim_start_matches('<')
+                        .trim_end_matches('>')
+                        .to_string()
+                })
+            })
+    };
+
+    next.filter(|next| !next.is_empty())
+        .map(|next| {
+            url.join(&next)
+                .map(String::from)
+                .map_err(|e| FetchError::Response(format!("Invalid next page URL {}: {}", next, e)))
+        })
+        .transpose()
+}
+
+/// The exit code for a response that was handled in full: 22 for an error
+/// status under `--fail` or `--fail-with-body`, otherwise 0.
+fn fail_code(cli: &Cli, status: StatusCode) -> i32 {
+    let error = status.is_client_error() || status.is_server_error();
+    if error && (cli.fail || cli.fail_with_body) {
+        22
+    } else {
+        0
+    }
+}
+
+/// The result of a request that completed.
+struct Outcome {
+    /// 0 on success, or the `--fail` code for an error status
+    exit_code: i32,
+    /// The next page to fetch when `--paginate` is set
+    next_page: Option<String>,
+    /// The value at the `--repeat-until` path, if the body had one
+    until_value: Option<String>,
+}
+
+impl Outcome {
+    fn last(exit_code: i32) -> Self {
+        Outcome {
+            exit_code,
+            next_page: None,
+            until_value: None,
+        }
+    }
+}
+
+/// Decodes a response body using `--charset`, the charset declared in
+/// Content-Type, or UTF-8, in that order.
+fn decode_body(cli: &Cli, headers: &HeaderMap, bytes: &[u8]) -> String {
+    let declared = || {
+        let content_type = headers.get(CONTENT_TYPE)?.to_str().ok()?;
+        content_type.split(';').skip(1).find_map(|param| {
+            let (name, value) = param.split_once('=')?;
+            if !name.trim().eq_ignore_ascii_case("charset") {
+                return None;
+            }
+            Encoding::for_label(value.trim().trim_matches('"').as_bytes())
+        })
+    };
+    let encoding = cli.charset.or_else(declared).unwrap_or(UTF_8);
+    encoding.decode(bytes).0.into_owned()
+}
+
+/// Prints the status line and response body in the format the flags ask for.
+fn print_response(
+    cli: &Cli,
+    version: reqwest::Version,
+    status: StatusCode,
+    response_headers: &HeaderMap,
+    bytes: &[u8],
+    body: &str,
+    printer: &mut Printer,
+) -> error::Result<()> {
+    if !cli.silent && cli.print.is_none() {
+        outln!(printer, "Status: {}", status);
+    }
+
+    if cli.include {
+        outln!(printer, "{}", format_headers(response_headers));
+    }
+
+    if let Some(print) = cli.print {
+        if print.response_headers {
+            outln!(printer, "{:?} {}", version, status);
+            outln!(printer, "{}", format_headers(response_headers));
+        }
+        if print.response_body && !body.is_empty() {
+            outln!(printer, "{}", format_response(cli, response_headers, body)?);
+        }
+    } else if cli.hexdump {
+        out!(printer, "{}", hexdump::hexdump(bytes));
+    } else if let Some(path) = &cli.jq {
+        let json: serde_json::Value = serde_json::from_str(body)
+            .map_err(|e| FetchError::Response(format!("Response body is not JSON: {}", e)))?;
+        match json_path(&json, path) {
+            Ok(value) => outln!(printer, "{}", format_json_value(value)),
+            Err(segment) => {
+                return Err(FetchError::Response(format!(
+                    "Path not found: {} (missing \"{}\")",
+                    path, segment
+                )));
+            }
+        }
+    } else if cli.silent {
+        // Silent output stays byte-for-byte unless a format was asked for
+        match cli.output_format {
+            OutputFormat::Auto | OutputFormat::Raw => out!(printer, "{}", body),
+            format => outln!(
+                printer,
+                "{}",
+                format_body(format, body, use_color(cli.color))?
+            ),
+        }
+    } else if body.is_empty() {
+        // Nothing to show (e.g. HEAD), so fall back to the response headers
+        if !cli.include {
+            out!(printer, "{}", format_headers(response_headers));
+        }
+    } else {
+        outln!(
+            printer,
+            "Body:\n{}",
+            format_response(cli, response_headers, body)?
+        );
+    }
+    Ok(())
+}
+
+/// Sends a single request and prints the response.
+///
+/// Returns the exit code for the request along with the next page, if any.
+/// Anything that stops the request from completing is returned as an error.
+async fn run_request(
+    cli: &Cli,
+    client: &reqwest::Client,
+    spec: RequestSpec,
+    index: usize,
+    printer: &mut Printer,
+) -> error::Result<Outcome> {
+    let RequestSpec {
+        name: _,
+        url,
+        method,
+        body: body_opt,
+        headers: headers_vec,
+        query,
+        output,
+        body_file,
+    } = spec;
+
+    let mut headers = HeaderMap::new();
+    for h in headers_vec {
+        let (name, value) = parse_header(&h)?;
+        if cli.replace_headers {
+            headers.insert(name, value);
+        } else {
+            headers.append(name, value);
+        }
+    }
+
+    // Auth from the config is only a default, so an Authorization header
+    // replaces it instead of conflicting
+    let config_auth_replaced = cli.auth_from_config && headers.contains_key(AUTHORIZATION);
+    if let Some(token) = cli.bearer.as_ref().filter(|_| !config_auth_replaced) {
+        if headers.contains_key(AUTHORIZATION) {
+            return Err(FetchError::Usage(
+                "Conflicting Authorization: use either --bearer or an Authorization --header"
+                    .to_string(),
+            ));
+        }
+        let mut value = HeaderValue::from_str(&format!("Bearer {}", token))
+            .map_err(|e| FetchError::Usage(format!("Invalid --bearer token: {}", e)))?;
+        value.set_sensitive(true);
+        headers.insert(AUTHORIZATION, value);
+    }
+
+    if let Some(agent) = &cli.user_agent {
+        if headers.contains_key(USER_AGENT) {
+            errln!(
+                printer,
+                "Warning: --user-agent overrides the User-Agent header"
+            );
+        }
+        let value = HeaderValue::from_str(agent)
+            .map_err(|e| FetchError::Usage(format!("Invalid --user-agent: {}", e)))?;
+        headers.insert(USER_AGENT, value);
+    }
+
+    let resume = match &output {
+        Some(path) => resume_offset(cli, path)?,
+        None => None,
+    };
+    if let Some(offset) = resume {
+        let value = HeaderValue::from_str(&format!("bytes={}-", offset))
+            .expect("a byte range is a valid header value");
+        headers.insert(RANGE, value);
+    }
+
+    if let Some(patch_type) = cli.patch_type {
+        let body = body_opt.as_deref().ok_or_else(|| {
+            FetchError::Usage("--patch-type needs a body, e.g. from --body or --json".to_string())
+        })?;
+        patch_type.validate(body)?;
+        if !headers.contains_key(CONTENT_TYPE) {
+            headers.insert(
+                CONTENT_TYPE,
+                HeaderValue::from_static(patch_type.content_type()),
+            );
+        }
+    }
+
+    if let Some(body) = &body_opt {
+        // Binary data is opaque, so its type is left to --header
+        let guess = !cli.no_auto_content_type && cli.data_binary.is_none();
+        if guess && !headers.contains_key(CONTENT_TYPE) {
+            let content_type = if serde_json::from_slice::<serde_json::Value>(body).is_ok() {
+                "application/json"
+            } else {
+                "text/plain"
+            };
+            headers.insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+        }
+    }
+
+    // Streamed files can't be sniffed, so go by the extension instead
+    let file_is_json = body_file.as_deref().is_some_and(|path| {
+        std::path::Path::new(path)
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("json"))
+    });
+    if body_file.is_some() && !cli.no_auto_content_type && !headers.contains_key(CONTENT_TYPE) {
+        let content_type = if file_is_json {
+            "application/json"
+        } else {
+            "application/octet-stream"
+        };
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+    }
+
+    // Ask for JSON unless the request itself is sending something else
+    let sends_json = match (&body_opt, &body_file) {
+        (Some(body), _) => serde_json::from_slice::<serde_json::Value>(body).is_ok(),
+        (None, Some(_)) => file_is_json,
+        (None, None) => cli.form.is_empty() && cli.field.is_empty() && cli.file_field.is_empty(),
+    };
+    if sends_json && !cli.no_default_accept && !headers.contains_key(ACCEPT) {
+        headers.insert(ACCEPT, HeaderValue::from_static("application/json"));
+    }
+
+    // Compress last, since the checks above need to see the original body
+    let body_opt = match body_opt {
+        Some(body) if cli.compress_request => {
+            if !headers.contains_key(CONTENT_ENCODING) {
+                headers.insert(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+            }
+            Some(gzip(&body))
+        }
+        body => body,
+    };
+
+    let method = if let Some(verb) = &cli.method_override {
+        let value = HeaderValue::from_str(&verb.to_uppercase())
+            .map_err(|e| FetchError::Usage(format!("Invalid --method-override: {}", e)))?;
+        headers.insert("x-http-method-override", value);
+        "POST".to_string()
+    } else if cli.head_only && method == "GET" {
+        "HEAD".to_string()
+    } else {
+        method
+    };
+
+    let method = Method::from_str(&method).map_err(|_| {
+        FetchError::Usage(format!(
+            "Invalid HTTP method {:?}: methods must be a single token, like GET or PROPFIND",
+            method
+        ))
+    })?;
+    let request_builder = client.request(method, &url);
+
+    let request_builder = match cli.user.as_ref().filter(|_| !config_auth_replaced) {
+        Some(credentials) => {
+            let (username, password) = credentials
+                .split_once(':')
+                .unwrap_or((credentials.as_str(), ""));
+            request_builder.basic_auth(username, Some(password))
+        }
+        None if (cli.netrc || cli.netrc_file.is_some())
+            && cli.bearer.is_none()
+            && cli.digest.is_none()
+            && !headers.contains_key(AUTHORIZATION) =>
+        {
+            let host = reqwest::Url::parse(&url)
+                .ok()
+                .and_then(|url| url.host_str().map(str::to_string));
+            match host {
+                Some(host) => match netrc::lookup(cli.netrc_file.as_deref(), &host)? {
+                    Some((login, password)) => request_builder.basic_auth(login, Some(password)),
+                    None => request_builder,
+                },
+                None => request_builder,
+            }
+        }
+        None => request_builder,
+    };
+
+    let request_builder = request_builder.query(&query).headers(headers);
+    let request_builder = if !cli.form.is_empty() {
+        request_builder.form(&cli.form)
+    } else if !cli.field.is_empty() || !cli.file_field.is_empty() {
+        request_builder.multipart(build_multipart(cli).await?)
+    } else if let Some(body) = body_opt {
+        check_upload_size(cli, body.len() as u64)?;
+        if cli.progress {
+            request_builder
+                .header(CONTENT_LENGTH, body.len())
+                .body(progress_body(body))
+        } else {
+            request_builder.body(body)
+        }
+    } else if let Some(path) = body_file {
+        let (body, len) = file_body(&path, cli.progress).await?;
+        check_upload_size(cli, len)?;
+        request_builder.header(CONTENT_LENGTH, len).body(body)
+    } else {
+        // No body at all, so strict servers don't see a stray Content-Length: 0
+        request_builder
+    };
+    let mut request = request_builder.build()?;
+    check_resolve_port(cli, request.url())?;
+
+    let cache = match &cli.cache_dir {
+        Some(dir) if request.method() == Method::GET => Some(cache::Cache::new(dir, request.url())),
+        _ => None,
+    };
+    if let Some(etag) = cache.as_ref().and_then(cache::Cache::etag)
+        && !request.headers().contains_key(IF_NONE_MATCH)
+        && let Ok(value) = HeaderValue::from_str(&etag)
+    {
+        request.headers_mut().insert(IF_NONE_MATCH, value);
+    }
+
+    // Signed last, since the signature covers every header added above
+    if let Some(scope) = &cli.aws_sigv4 {
+        sigv4::Signer::new(
+            scope,
+            cli.aws_access_key.as_deref(),
+            cli.aws_secret_key.as_deref(),
+        )?
+        .sign(&mut request)?;
+    }
+
+    if cli.dry_run {
+        out!(printer, "{}", format_request(&request));
+        return Ok(Outcome::last(0));
+    }
+
+    if cli.offline {
+        let Some(cache) = &cache else {
+            return Err(FetchError::Usage(format!(
+                "--offline can only serve GET requests, not {}",
+                request.method()
+            )));
+        };
+        let (status, headers, bytes) = cache.response().ok_or_else(|| {
+            FetchError::Response(format!("Not cached (--offline): {}", request.url()))
+        })?;
+        errln!(printer, "Served from cache (offline)");
+        if let Some(output_path) = &output {
+            download::write(output_path, &bytes)?;
+            return Ok(Outcome::last(0));
+        }
+        let received = Received {
+            status,
+            version: reqwest::Version::HTTP_11,
+            headers,
+            url: request.url().clone(),
+            bytes,
+            elapsed: Duration::ZERO,
+        };
+        return show_response(cli, index, &received, printer);
+    }
+
+    if let Some(print) = cli.print {
+        if print.request_headers {
+            outln!(printer, "{} {}", request.method(), request.url());
+            for (name, value) in request.headers() {
+                if value.is_sensitive() {
+                    outln!(printer, "{}: <redacted>", name);
+                } else {
+                    outln!(
+                        printer,
+                        "{}: {}",
+                        name,
+                        String::from_utf8_lossy(value.as_bytes())
+                    );
+                }
+            }
+            outln!(printer);
+        }
+        if let Some(body) = request_body_text(&request)
+            && print.request_body
+        {
+            outln!(printer, "{}\n", body);
+        }
+    }
+
+    if cli.trace {
+        errln!(printer, "== Request ==");
+        err!(printer, "{}", format_request(&request));
+    } else if cli.verbose {
+        err!(printer, "{}", format_request(&request));
+    }
+    if cli.verbose || cli.trace {
+        let length = match request.body() {
+            Some(body) => body
+                .as_bytes()
+                .map(|bytes| bytes.len() as u64)
+                .or_else(|| content_length(request.headers())),
+            None => Some(0),
+        };
+        match length {
+            Some(length) => errln!(printer, "* Request body: {} bytes", length),
+            None => errln!(printer, "* Request body: streamed, length unknown"),
+        }
+    }
+
+    // Waiting for --rate-limit isn't part of the request's time
+    rate_limit::acquire().await;
+    let log_entry = cli
+        .log_file
+        .as_ref()
+        .map(|_| log::Entry::new(&request, cli.log_secrets));
+    let har_request = cli.har.as_ref().map(|_| har::Request::new(&request));
+    let start = Instant::now();
+    let log = |status: Option<StatusCode>, size: Option<usize>, error: Option<&str>| match (
+        &cli.log_file,
+        &log_entry,
+    ) {
+        (Some(path), Some(entry)) => entry.write(path, status, size, start.elapsed(), error),
+        _ => Ok(()),
+    };
+
+    let response = match send_request(cli, client, request, printer).await {
+        Ok(response) => response,
+        Err(e) => {
+            log(None, None, Some(&e.to_string()))?;
+            return Err(e);
+        }
+    };
+    let status = response.status();
+    let response_headers = response.headers().clone();
+    let response_url = response.url().clone();
+    let version = response.version();
+    let record = |body: Option<&[u8]>| {
+        if let Some(har_request) = &har_request {
+            har_request.record(status, version, &response_headers, body, start.elapsed());
+        }
+    };
+
+    if cli.trace {
+        errln!(printer, "== Response ==");
+        errln!(printer, "< {:?} {}", version, status);
+        for (name, value) in &response_headers {
+            errln!(
+                printer,
+                "< {}: {}",
+                name,
+                String::from_utf8_lossy(value.as_bytes())
+            );
+        }
+        errln!(printer, "<");
+    } else if cli.verbose {
+        errln!(printer, "< {:?} {}", version, status);
+    }
+    if let Some(path) = &cli.output_headers {
+        write_headers(path, version, status, &response_headers)?;
+    }
+
+    let failed = cli.fail && (status.is_client_error() || status.is_server_error());
+    if cli.status_only {
+        log(Some(status), None, None)?;
+        record(None);
+        outln!(printer, "{}", status.as_u16());
+        print_timing(cli, start.elapsed(), printer);
+        check_expectations(cli, status, "")?;
+        return Ok(Outcome::last(fail_code(cli, status)));
+    }
+
+    if failed {
+        log(Some(status), None, None)?;
+        record(None);
+        if !cli.silent {
+            errln!(printer, "Status: {}", status);
+        }
+        print_timing(cli, start.elapsed(), printer);
+        return Ok(Outcome::last(22));
+    }
+
+    if cli.no_body {
+        let mut response = response;
+        let mut received = 0;
+        while let Some(chunk) = response.chunk().await.map_err(|e| body_error(cli, e))? {
+            received += chunk.len();
+        }
+        log(Some(status), Some(received), None)?;
+        record(None);
+        if !cli.silent {
+            outln!(printer, "Status: {}", status);
+        }
+        if cli.include {
+            out!(printer, "{}", format_headers(&response_headers));
+        }
+        errln!(printer, "Discarded {} byte response body", received);
+        report_transfer(cli, &response_headers, received, printer);
+        print_timing(cli, start.elapsed(), printer);
+        check_expectations(cli, status, "")?;
+        return Ok(Outcome::last(fail_code(cli, status)));
+    }
+
+    if cli.head_only {
+        // Dropping the response closes the connection before the body is read
+        drop(response);
+        log(Some(status), None, None)?;
+        record(None);
+        outln!(printer, "Status: {}", status);
+        out!(printer, "{}", format_headers(&response_headers));
+        print_timing(cli, start.elapsed(), printer);
+        check_expectations(cli, status, "")?;
+        return Ok(Outcome::last(fail_code(cli, status)));
+    }
+
+    if let Some(output_path) = &output {
+        // Keep the raw bytes so binary downloads survive untouched
+        let mut download = match resume {
+            Some(offset) if status == StatusCode::PARTIAL_CONTENT => {
+                let start = range_start(&response_headers);
+                if start != Some(offset) {
+                    let e = FetchError::Response(format!(
+                        "The server sent a range starting at {}, not at byte {}; leaving the download of {} unchanged",
+                        start.map_or("an unknown offset".to_string(), |start| start.to_string()),
+                        offset,
+                        output_path
+                    ));
+                    log(Some(status), None, Some(&e.to_string()))?;
+                    return Err(e);
+                }
+                Some(download::Download::resume(output_path)?)
+            }
+            Some(_) if status.is_success() => {
+                errln!(
+                    printer,
+                    "Warning: the server ignored the range and sent the whole file; overwriting {}",
+                    output_path
+                );
+                Some(download::Download::create(output_path)?)
+            }
+            // An error body would clobber the part already downloaded
+            Some(_) => {
+                errln!(printer, "Leaving the download of {} unchanged", output_path);
+                None
+            }
+            None => Some(download::Download::create(output_path)?),
+        };
+        let mut bytes = match read_body(cli, response, download.as_mut()).await {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                // Kept for --continue-at, like a download cut short by Ctrl-C
+                if let Some(download) = &download {
+                    errln!(printer, "Partial download kept in {}", download.partial());
+                }
+                return Err(e);
+            }
+        };
+        log(Some(status), Some(bytes.len()), None)?;
+        record(Some(&bytes));
+        if let Some(cache) = &cache {
+            bytes = cache.update(status, &response_headers, bytes, printer)?;
+            // A 304 has no body of its own, so the cached one is saved instead
+            if let (StatusCode::NOT_MODIFIED, Some(download)) = (status, download.as_mut()) {
+                download.write(&bytes)?;
+            }
+        }
+        trace_body(cli, &bytes, printer);
+        report_transfer(cli, &response_headers, bytes.len(), printer);
+        print_timing(cli, start.elapsed(), printer);
+        if let Some(download) = download {
+            download.finish()?;
+        }
+        if !cli.silent {
+            errln!(printer, "Status: {}", status);
+        }
+        if cli.include {
+            err!(printer, "{}", format_headers(&response_headers));
+        }
+        check_expectations(cli, status, &decode_body(cli, &response_headers, &bytes))?;
+        return Ok(Outcome::last(fail_code(cli, status)));
+    }
+
+    let mut bytes = read_body(cli, response, None).await?;
+    log(Some(status), Some(bytes.len()), None)?;
+    record(Some(&bytes));
+    if let Some(cache) = &cache {
+        bytes = cache.update(status, &response_headers, bytes, printer)?;
+    }
+    trace_body(cli, &bytes, printer);
+    report_transfer(cli, &response_headers, bytes.len(), printer);
+    let received = Received {
+        status,
+        version,
+        headers: response_headers,
+        url: response_url,
+        bytes,
+        elapsed: start.elapsed(),
+    };
+    show_response(cli, index, &received, printer)
+}
+
+/// A response read in full, from the network or from `--cache-dir`
+struct Received {
+    status: StatusCode,
+    version: reqwest::Version,
+    headers: HeaderMap,
+    url: reqwest::Url,
+    bytes: Vec<u8>,
+    elapsed: Duration,
+}
+
+/// Prints a fully read response and runs the checks that need its body.
+fn show_response(
+    cli: &Cli,
+    index: usize,
+    received: &Received,
+    printer: &mut Printer,
+) -> error::Result<Outcome> {
+    let status = received.status;
+    let response_headers = &received.headers;
+    let response_url = &received.url;
+    let body = decode_body(cli, response_headers, &received.bytes);
+
+    if cli.json_lines {
+        let line = serde_json::json!({
+            "index": index,
+            "url": response_url.as_str(),
+            "status": status.as_u16(),
+            "body": body,
+        });
+        outln!(printer, "{}", line);
+    } else if let Some(template) = &cli.template {
+        let context = template::Context {
+            status,
+            url: response_url.as_str(),
+            headers: response_headers,
+            body: &body,
+            time: received.elapsed,
+        };
+        outln!(
+            printer,
+            "{}",
+            template::Template::parse(template)?.render(&context)?
+        );
+    } else {
+        print_response(
+            cli,
+            received.version,
+            status,
+            response_headers,
+            &received.bytes,
+            &body,
+            printer,
+        )?;
+    }
+
+    print_timing(cli, received.elapsed, printer);
+    check_expectations(cli, status, &body)?;
+    if cli.graphql.is_some() {
+        check_graphql_errors(&body, printer)?;
+    }
+
+    let next_page = if cli.paginate {
+        next_page_url(cli, response_url, response_headers, &body)?
+    } else {
+        None
+    };
+    let until_value = cli.repeat_until.as_ref().and_then(|(path, _)| {
+        let json = serde_json::from_str(&body).ok()?;
+        json_path(&json, path).ok().map(format_json_value)
+    });
+    Ok(Outcome {
+        exit_code: fail_code(cli, status),
+        next_page,
+        until_value,
+    })
+}
+
+/// Sends the request until the `--repeat-until` value matches or
+/// `--max-attempts` runs out, showing only the last response in full.
+async fn repeat_until(
+    cli: &Cli,
+    client: &reqwest::Client,
+    spec: &RequestSpec,
+    index: usize,
+    printer: &mut Printer,
+) -> error::Result<Outcome> {
+    let (path, expected) = cli.repeat_until.as_ref().expect("checked by caller");
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let mut attempt_printer = Printer::buffered();
+        let result = run_request(cli, client, spec.clone(), index, &mut attempt_printer).await;
+        // A failed attempt is reported like --wait-for does and polled again
+        let failure = match &result {
+            Ok(outcome) if outcome.exit_code == 0 => {
+                let value = outcome.until_value.as_deref();
+                errln!(
+                    printer,
+                    "Attempt {}: {} = {}",
+                    attempt,
+                    path,
+                    value.unwrap_or("<missing>")
+                );
+                if value == Some(expected.as_str()) {
+                    printer.append(attempt_printer);
+                    return result;
+                }
+                None
+            }
+            Ok(outcome) => Some(format!("exit code {}", outcome.exit_code)),
+            Err(e) => Some(e.to_string()),
+        };
+        if let Some(failure) = &failure {
+            errln!(printer, "Attempt {}: {}", attempt, failure);
+        }
+
+        if attempt >= cli.max_attempts {
+            printer.append(attempt_printer);
+            let mut message = format!(
+                "expected {} to be {:?} within {} attempt(s)",
+                path, expected, attempt
+            );
+            if let Some(failure) = failure {
+                message.push_str(&format!(", the last failed with {}", failure));
+            }
+            return Err(FetchError::Expectation(vec![message]));
+        }
+        // Polling back-to-back would hammer the server, so wait like --wait-for
+        let secs = cli.interval.unwrap_or(1.0);
+        tokio::time::sleep(Duration::from_secs_f64(secs)).await;
+    }
+}
+
+/// Sends the request until it succeeds or `--wait-timeout` runs out.
+///
+/// Only the final attempt's output is shown; earlier attempts just report
+/// why they weren't ready yet.
+async fn wait_for(
+    cli: &Cli,
+    client: &reqwest::Client,
+    spec: &RequestSpec,
+    index: usize,
+    printer: &mut Printer,
+) -> error::Result<Outcome> {
+    let start = Instant::now();
+    let timeout = Duration::from_secs_f64(cli.wait_timeout);
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let mut attempt_printer = Printer::buffered();
+        let result = run_request(cli, client, spec.clone(), index, &mut attempt_printer).await;
+        let reason = match &result {
+            Ok(outcome) if outcome.exit_code == 0 => {
+                printer.append(attempt_printer);
+                return result;
+            }
+            Ok(outcome) => format!("exit code {}", outcome.exit_code),
+            Err(e) => e.to_string(),
+        };
+
+        let remaining = timeout.saturating_sub(start.elapsed());
+        if remaining.is_zero() {
+            printer.append(attempt_printer);
+            return Err(FetchError::Response(format!(
+                "Not ready after {}s and {} attempt(s): {}",
+                cli.wait_timeout, attempt, reason
+            )));
+        }
+        errln!(printer, "Not ready (attempt {}): {}", attempt, reason);
+        tokio::time::sleep(remaining.min(Duration::from_secs_f64(cli.wait_interval))).await;
+    }
+}
+
+/// Runs one entry of the request list, including its batch separator and
+/// any `--repeat` iterations.
+///
+/// Errors are reported through `printer`. Returns the exit code of the last
+/// failing attempt, or 0 when every attempt succeeded.
+async fn run_spec(
+    cli: &Cli,
+    client: &reqwest::Client,
+    spec: RequestSpec,
+    index: usize,
+    total: usize,
+    batch: bool,
+    printer: &mut Printer,
+) -> i32 {
+    // JSON lines carry everything in the line itself, so skip the markers
+    let markers = !cli.json_lines;
+    if batch && markers {
+        if index > 0 {
+            outln!(printer);
+        }
+        outln!(printer, "--- Request {}/{} ---", index + 1, total);
+    }
+    if let (Some(name), true) = (&spec.name, markers) {
+        outln!(printer, "=== {} ===", name);
+    }
+
+    let mut exit_code = 0;
+    for iteration in 0..cli.repeat {
+        if iteration > 0
+            && let Some(secs) = cli.interval
+        {
+            tokio::time::sleep(Duration::from_secs_f64(secs)).await;
+        }
+        if cli.repeat > 1 && markers {
+            outln!(
+                printer,
+                "--- Iteration {}/{} ---",
+                iteration + 1,
+                cli.repeat
+            );
+        }
+
+        let mut page_spec = spec.clone();
+        let mut page = 1;
+        let code = loop {
+            if cli.paginate && !cli.silent && markers {
+                outln!(printer, "--- Page {} ---", page);
+            }
+            let result = if cli.wait_for {
+                wait_for(cli, client, &page_spec, index, printer).await
+            } else if cli.repeat_until.is_some() {
+                repeat_until(cli, client, &page_spec, index, printer).await
+            } else {
+                run_request(cli, client, page_spec.clone(), index, printer).await
+            };
+            match result {
+                Ok(Outcome {
+                    exit_code: 0,
+                    next_page: Some(next),
+                    ..
+                }) if cli.max_pages.is_none_or(|max| page < max) => {
+                    // The next link already carries its own query string
+                    page_spec.url = next;
+                    page_spec.query.clear();
+                    page += 1;
+                }
+                Ok(outcome) => break outcome.exit_code,
+                Err(e) if cli.json_lines => {
+                    let line = serde_json::json!({
+                        "index": index,
+                        "url": page_spec.url,
+                        "error": e.to_string(),
+                    });
+                    outln!(printer, "{}", line);
+                    break e.exit_code();
+                }
+                Err(e) => {
+                    errln!(printer, "Error: {}", e);
+                    break e.exit_code();
+                }
+            }
+        };
+
+        if code != 0 {
+            exit_code = code;
+            if cli.stop_on_error {
+                break;
+            }
+        }
+    }
+    exit_code
+}
+
+/// Resolves every request and runs them, returning the exit code of the
+/// last failing request (0 if all succeeded).
+///
+/// Errors that stop fetch before any request is sent are returned directly.
+async fn run(mut cli: Cli) -> error::Result<i32> {
+    // Catch template mistakes before anything is sent
+    if let Some(template) = &cli.template {
+        template::Template::parse(template)?;
+    }
+    if let Some(path) = &cli.schema {
+        load_schema(path)?;
+    }
+    if let Some(path) = &cli.diff {
+        std::fs::read_to_string(path).map_err(|e| FetchError::read(path, e))?;
+    }
+
+    let mut config = config::load(cli.config.as_deref())?;
+    if let Some(name) = &cli.env {
+        config = config.select(name)?;
+    }
+    config.apply(&mut cli);
+    if cli.no_pretty {
+        cli.output_format = OutputFormat::Raw;
+    }
+    if let Some(rate) = cli.rate_limit {
+        rate_limit::init(rate);
+    }
+
+    let cookie_jar = match &cli.cookie_jar {
+        Some(path) => Some(Arc::new(CookieStoreMutex::new(load_cookie_jar(path)?))),
+        // A session keeps its cookies in memory even without a jar file
+        None if cli.interactive => Some(Arc::new(CookieStoreMutex::default())),
+        None => None,
+    };
+    let client = build_client(&cli, cookie_jar.clone())?;
+
+    let (requests, batch) = if let Some(file_path) = &cli.file {
+        match request_file::load(file_path)? {
+            RequestFileContent::Single(file) => (vec![RequestSpec::from(file)], false),
+            RequestFileContent::Batch(files) => {
+                (files.into_iter().map(RequestSpec::from).collect(), true)
+            }
+        }
+    } else if cli.interactive {
+        (Vec::new(), false)
+    } else if let Some(hosts_path) = &cli.hosts_file {
+        let template = cli_request(&cli)?;
+        let hosts = read_lines(hosts_path)?;
+        let specs = hosts
+            .into_iter()
+            .map(|url| RequestSpec {
+                name: Some(url.clone()),
+                url,
+                ..template.clone()
+            })
+            .collect();
+        (specs, true)
+    } else {
+        (vec![cli_request(&cli)?], false)
+    };
+
+    let base_url = cli
+        .base_url
+        .as_deref()
+        .map(reqwest::Url::parse)
+        .transpose()
+        .map_err(|e| FetchError::Usage(format!("Invalid --base-url: {}", e)))?;
+    if let Some(dir) = &cli.output_dir {
+        std::fs::create_dir_all(dir).map_err(|e| FetchError::write(dir, e))?;
+    }
+    // Each response appends its headers, so start from an empty file
+    if let Some(path) = &cli.output_headers {
+        std::fs::write(path, "").map_err(|e| FetchError::write(path, e))?;
+    }
+    let requests = requests
+        .into_iter()
+        .enumerate()
+        .map(|(index, mut spec)| {
+            spec.url = resolve_url(base_url.as_ref(), spec.url)?;
+            spec.query = cli.query.clone();
+            spec.output = match &cli.output_dir {
+                Some(dir) => {
+                    let label = match &spec.name {
+                        Some(name) => file_name_slug(name),
+                        None => spec.method.to_lowercase(),
+                    };
+                    let name = format!("{}-{}.txt", index + 1, label);
+                    Some(std::path::Path::new(dir).join(name).display().to_string())
+                }
+                None => cli.output.clone(),
+            };
+            Ok(spec)
+        })
+        .collect::<error::Result<Vec<_>>>()?;
+
+    // The header file replaces config headers, --header-from-env replaces
+    // both, and --header replaces everything
+    let mut default_headers = config.header;
+    if let Some(path) = &cli.header_file {
+        default_headers = layer_headers(default_headers, read_lines(path)?);
+    }
+    default_headers = layer_headers(default_headers, env_headers(&cli)?);
+    for spec in &requests {
+        confirm_upload(&cli, spec)?;
+    }
+
+    let cli = Arc::new(cli);
+    let work = async {
+        if cli.interactive {
+            // No cli_request here to add --header, so the session takes it
+            let headers = layer_headers(default_headers, cli.header.clone());
+            return repl::run(&cli, &client, base_url, headers).await;
+        }
+
+        let total = requests.len();
+        let requests = requests.into_iter().map(|mut spec| {
+            spec.headers = layer_headers(default_headers.clone(), spec.headers);
+            spec
+        });
+
+        let mut exit_code = 0;
+        if cli.concurrency > 1 {
+            let semaphore = Arc::new(Semaphore::new(cli.concurrency as usize));
+            let mut tasks = Vec::with_capacity(total);
+            for (index, spec) in requests.enumerate() {
+                let (cli, client, semaphore) = (cli.clone(), client.clone(), semaphore.clone());
+                tasks.push(tokio::spawn(async move {
+                    let _permit = semaphore.acquire_owned().await;
+                    let mut printer = Printer::buffered();
+                    let code =
+                        run_spec(&cli, &client, spec, index, total, batch, &mut printer).await;
+                    (code, printer)
+                }));
+            }
+
+            // Await in file order so output comes out in file order too
+            let mut tasks = tasks.into_iter();
+            for task in tasks.by_ref() {
+                let (code, printer) = task.await.expect("request task panicked");
+                printer.flush();
+                if code != 0 {
+                    exit_code = code;
+                    if cli.stop_on_error {
+                        break;
+                    }
+                }
+            }
+            tasks.for_each(|task| task.abort());
+        } else {
+            for (index, spec) in requests.enumerate() {
+                let mut printer = Printer::direct();
+                let code = run_spec(&cli, &client, spec, index, total, batch, &mut printer).await;
+                if code != 0 {
+                    exit_code = code;
+                    if cli.stop_on_error {
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(exit_code)
+    };
+
+    // Dropping the work cancels whatever request is in flight, while the
+    // cookies and HAR entries collected so far are still saved below
+    let exit_code = tokio::select! {
+        result = work => result?,
+        _ = tokio::signal::ctrl_c() => {
+            eprintln!("Interrupted: the request was cancelled");
+            for partial in download::unfinished() {
+                eprintln!("Partial download kept in {}", partial);
+            }
+            130
+        }
+    };
+
+    if let Some(path) = &cli.har {
+        har::write(path)?;
+    }
+    if let (Some(path), Some(jar)) = (&cli.cookie_jar, &cookie_jar) {
+        save_cookie_jar(path, jar)?;
+    }
+
+    Ok(exit_code)
+}
+
+#[tokio::main]
+async fn main() {
+    let exit_code = match run(Cli::parse()).await {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            e.exit_code()
+        }
+    };
+    std::process::exit(exit_code);
 }
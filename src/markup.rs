@@ -0,0 +1,253 @@
+//! Indenting XML and HTML response bodies.
+//!
+//! This is a formatter, not a validating parser: it only needs to find tag
+//! boundaries. Malformed XML makes [`pretty`] give up so the body is shown
+//! raw; HTML is formatted leniently, the way browsers read it.
+
+/// Which markup rules to format with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Xml,
+    Html,
+}
+
+impl Kind {
+    /// Picks the kind from a Content-Type such as `application/rss+xml`.
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let mime = content_type.split(';').next()?.trim().to_ascii_lowercase();
+        if mime == "text/html" || mime == "application/xhtml+xml" {
+            Some(Kind::Html)
+        } else if mime == "application/xml" || mime == "text/xml" || mime.ends_with("+xml") {
+            Some(Kind::Xml)
+        } else {
+            None
+        }
+    }
+}
+
+/// Elements that never have content or an end tag in HTML
+const VOID_ELEMENTS: [&str; 14] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+/// HTML elements whose content is kept exactly as written
+const RAW_TEXT_ELEMENTS: [&str; 4] = ["script", "style", "pre", "textarea"];
+
+enum Token<'a> {
+    Open {
+        name: String,
+        raw: &'a str,
+    },
+    Close {
+        name: String,
+        raw: &'a str,
+    },
+    /// A self-closing or void tag, comment, declaration, CDATA section, or
+    /// an HTML raw-text element with its content
+    Standalone(&'a str),
+    Text(&'a str),
+}
+
+/// Indents `input` two spaces per level, or returns `None` if it can't be
+/// read as `kind`.
+pub fn pretty(input: &str, kind: Kind) -> Option<String> {
+    let tokens = tokenize(input, kind)?;
+    let mut out = String::new();
+    let mut stack: Vec<String> = Vec::new();
+    let mut line = |depth: usize, text: &str| {
+        out.push_str(&"  ".repeat(depth));
+        out.push_str(text);
+        out.push('\n');
+    };
+
+    let mut i = 0;
+    while i < tokens.len() {
+        match &tokens[i] {
+            Token::Open { name, raw } => {
+                // Keep `<a>text</a>` on one line
+                if let (
+                    Some(Token::Text(text)),
+                    Some(Token::Close {
+                        name: close,
+                        raw: end,
+                    }),
+                ) = (tokens.get(i + 1), tokens.get(i + 2))
+                    && close == name
+                {
+                    line(stack.len(), &format!("{}{}{}", raw, text, end));
+                    i += 3;
+                    continue;
+                }
+                line(stack.len(), raw);
+                stack.push(name.clone());
+            }
+            Token::Close { name, raw } => match stack.iter().rposition(|open| open == name) {
+                // XML end tags must match the innermost open element
+                Some(position) if kind == Kind::Html || position + 1 == stack.len() => {
+                    stack.truncate(position);
+                    line(stack.len(), raw);
+                }
+                None if kind == Kind::Html => line(stack.len(), raw),
+                _ => return None,
+            },
+            Token::Standalone(raw) | Token::Text(raw) => line(stack.len(), raw),
+        }
+        i += 1;
+    }
+
+    if kind == Kind::Xml && !stack.is_empty() {
+        return None;
+    }
+    Some(out.trim_end().to_string())
+}
+
+fn tokenize(input: &str, kind: Kind) -> Option<Vec<Token<'_>>> {
+    let mut tokens = Vec::new();
+    let mut rest = input;
+
+    while !rest.is_empty() {
+        if !rest.starts_with('<') {
+            let end = rest.find('<').unwrap_or(rest.len());
+            let text = rest[..end].trim();
+            if !text.is_empty() {
+                tokens.push(Token::Text(text));
+            }
+            rest = &rest[end..];
+            continue;
+        }
+
+        let delimited = [("<!--", "-->"), ("<![CDATA[", "]]>"), ("<?", "?>")];
+        if let Some((_, close)) = delimited.iter().find(|(open, _)| rest.starts_with(open)) {
+            let end = rest.find(close)? + close.len();
+            tokens.push(Token::Standalone(&rest[..end]));
+            rest = &rest[end..];
+            continue;
+        }
+
+        let end = tag_end(rest)?;
+        let raw = &rest[..end];
+        rest = &rest[end..];
+        if raw.starts_with("<!") {
+            tokens.push(Token::Standalone(raw));
+            continue;
+        }
+
+        let closing = raw.starts_with("</");
+        let name: String = raw[if closing { 2 } else { 1 }..]
+            .chars()
+            .take_while(|c| !c.is_whitespace() && *c != '>' && *c != '/')
+            .collect();
+        if name.is_empty() {
+            return None;
+        }
+        // HTML tag names are case-insensitive
+        let name = match kind {
+            Kind::Html => name.to_ascii_lowercase(),
+            Kind::Xml => name,
+        };
+
+        if closing {
+            tokens.push(Token::Close { name, raw });
+        } else if raw.ends_with("/>") || (kind == Kind::Html && VOID_ELEMENTS.contains(&&*name)) {
+            tokens.push(Token::Standalone(raw));
+        } else if kind == Kind::Html && RAW_TEXT_ELEMENTS.contains(&&*name) {
+            // Everything up to the end tag is content, even `<` characters
+            let content_end = find_ignore_case(rest, &format!("</{}", name)).unwrap_or(rest.len());
+            let close_end = rest[content_end..]
+                .find('>')
+                .map_or(rest.len(), |i| content_end + i + 1);
+            let start = input.len() - rest.len() - raw.len();
+            let whole_end = input.len() - rest.len() + close_end;
+            tokens.push(Token::Standalone(&input[start..whole_end]));
+            rest = &rest[close_end..];
+        } else {
+            tokens.push(Token::Open { name, raw });
+        }
+    }
+    Some(tokens)
+}
+
+/// The length of the tag at the start of `input`, skipping `>` inside
+/// quoted attribute values.
+fn tag_end(input: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in input.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), _) if c == q => quote = None,
+            (None, '>') => return Some(i + 1),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn find_ignore_case(haystack: &str, needle: &str) -> Option<usize> {
+    haystack
+        .to_ascii_lowercase()
+        .find(&needle.to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indents_nested_xml() {
+        let input = r#"<?xml version="1.0"?><feed><entry id="1"><title>A &amp; B</title><link href="/a"/></entry><!-- end --></feed>"#;
+        assert_eq!(
+            pretty(input, Kind::Xml).unwrap(),
+            "<?xml version=\"1.0\"?>\n\
+             <feed>\n  \
+               <entry id=\"1\">\n    \
+                 <title>A &amp; B</title>\n    \
+                 <link href=\"/a\"/>\n  \
+               </entry>\n  \
+               <!-- end -->\n\
+             </feed>"
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_xml() {
+        assert_eq!(pretty("<a><b></a></b>", Kind::Xml), None);
+        assert_eq!(pretty("<a><b></b>", Kind::Xml), None);
+        assert_eq!(pretty("<a></b>", Kind::Xml), None);
+        assert_eq!(pretty("<a attr=\"x", Kind::Xml), None);
+    }
+
+    #[test]
+    fn keeps_html_void_and_raw_text_elements() {
+        let input = "<DIV><br><img src=\"a>b.png\"><pre>  <b>x</b>\n  y</pre></div>";
+        assert_eq!(
+            pretty(input, Kind::Html).unwrap(),
+            "<DIV>\n  \
+               <br>\n  \
+               <img src=\"a>b.png\">\n  \
+               <pre>  <b>x</b>\n  y</pre>\n\
+             </div>"
+        );
+    }
+
+    #[test]
+    fn tolerates_unbalanced_html() {
+        assert_eq!(
+            pretty("<ul><li>one<li>two</ul></p>", Kind::Html).unwrap(),
+            "<ul>\n  <li>\n    one\n    <li>\n      two\n</ul>\n</p>"
+        );
+    }
+
+    #[test]
+    fn picks_kind_from_content_type() {
+        assert_eq!(
+            Kind::from_content_type("text/html; charset=utf-8"),
+            Some(Kind::Html)
+        );
+        assert_eq!(
+            Kind::from_content_type("application/rss+xml"),
+            Some(Kind::Xml)
+        );
+        assert_eq!(Kind::from_content_type("application/json"), None);
+    }
+}
@@ -0,0 +1,94 @@
+//! Looking up credentials in a `.netrc` file for `--netrc`.
+//!
+//! Supports `machine`, `default`, `login`, and `password` entries; `account`
+//! values and `macdef` macros are skipped.
+
+use crate::error::{self, FetchError};
+use std::path::PathBuf;
+
+/// `~/.netrc`, using `USERPROFILE` as the home when `HOME` isn't set.
+fn default_path() -> Option<PathBuf> {
+    let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"))?;
+    Some(PathBuf::from(home).join(".netrc"))
+}
+
+/// The login and password for `host`, falling back to the `default` entry.
+///
+/// A missing default file means no credentials, but a missing `path` given
+/// with `--netrc-file` is an error.
+pub fn lookup(path: Option<&str>, host: &str) -> error::Result<Option<(String, String)>> {
+    let path = match path {
+        Some(path) => PathBuf::from(path),
+        None => match default_path() {
+            Some(path) if path.exists() => path,
+            _ => return Ok(None),
+        },
+    };
+    let display = path.display().to_string();
+    let content = std::fs::read_to_string(&path).map_err(|e| FetchError::read(&display, e))?;
+    Ok(find(&content, host))
+}
+
+/// One `machine` or `default` entry
+#[derive(Default)]
+struct Entry<'a> {
+    /// `None` for the `default` entry
+    machine: Option<&'a str>,
+    login: &'a str,
+    password: &'a str,
+}
+
+fn find(content: &str, host: &str) -> Option<(String, String)> {
+    let mut tokens = Tokens::new(content);
+    let mut entries: Vec<Entry> = Vec::new();
+
+    while let Some(token) = tokens.next() {
+        match (token, entries.last_mut()) {
+            ("machine", _) => entries.push(Entry {
+                machine: Some(tokens.next().unwrap_or_default()),
+                ..Entry::default()
+            }),
+            ("default", _) => entries.push(Entry::default()),
+            ("login", Some(entry)) => entry.login = tokens.next().unwrap_or_default(),
+            ("password", Some(entry)) => entry.password = tokens.next().unwrap_or_default(),
+            ("account", _) => {
+                tokens.next();
+            }
+            ("macdef", _) => tokens.skip_macro(),
+            _ => {}
+        }
+    }
+
+    let entry = entries
+        .iter()
+        .find(|entry| entry.machine == Some(host))
+        .or_else(|| entries.iter().find(|entry| entry.machine.is_none()))?;
+    Some((entry.login.to_string(), entry.password.to_string()))
+}
+
+/// Whitespace-separated netrc tokens
+struct Tokens<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Tokens<'a> {
+    fn new(content: &'a str) -> Self {
+        Tokens { rest: content }
+    }
+
+    fn next(&mut self) -> Option<&'a str> {
+        let start = self.rest.find(|c: char| !c.is_whitespace())?;
+        let rest = &self.rest[start..];
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        self.rest = &rest[end..];
+        Some(&rest[..end])
+    }
+
+    /// Skips a `macdef` name and body, which runs until the next blank line.
+    fn skip_macro(&mut self) {
+        self.rest = match self.rest.find("\n\n") {
+            Some(end) => &self.rest[end..],
+            None => "",
+        };
+    }
+}
@@ -0,0 +1,95 @@
+//! Request output that can be printed immediately or held back.
+//!
+//! Concurrent requests finish in any order, so each one writes into its own
+//! buffered `Printer` and the buffers are replayed in request order.
+
+use std::io::Write;
+
+enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// Collects stdout/stderr output for a single request.
+pub struct Printer {
+    buffer: Option<Vec<(Stream, String)>>,
+}
+
+impl Printer {
+    /// A printer that writes straight to the terminal.
+    pub fn direct() -> Self {
+        Printer { buffer: None }
+    }
+
+    /// A printer that keeps everything until `flush` is called.
+    pub fn buffered() -> Self {
+        Printer {
+            buffer: Some(Vec::new()),
+        }
+    }
+
+    pub fn out(&mut self, text: String) {
+        self.write(Stream::Stdout, text);
+    }
+
+    pub fn err(&mut self, text: String) {
+        self.write(Stream::Stderr, text);
+    }
+
+    fn write(&mut self, stream: Stream, text: String) {
+        match (&mut self.buffer, stream) {
+            (Some(buffer), stream) => buffer.push((stream, text)),
+            (None, Stream::Stdout) => print!("{}", text),
+            (None, Stream::Stderr) => eprint!("{}", text),
+        }
+    }
+
+    /// Writes everything another buffered printer has collected.
+    pub fn append(&mut self, other: Printer) {
+        for (stream, text) in other.buffer.unwrap_or_default() {
+            self.write(stream, text);
+        }
+    }
+
+    /// Replays buffered output in the order it was written.
+    pub fn flush(self) {
+        for (stream, text) in self.buffer.unwrap_or_default() {
+            match stream {
+                Stream::Stdout => print!("{}", text),
+                Stream::Stderr => eprint!("{}", text),
+            }
+        }
+        let _ = std::io::stdout().flush();
+    }
+}
+
+/// Like `print!`, but through a `Printer`.
+macro_rules! out {
+    ($printer:expr, $($arg:tt)*) => {
+        $printer.out(format!($($arg)*))
+    };
+}
+
+/// Like `println!`, but through a `Printer`.
+macro_rules! outln {
+    ($printer:expr) => {
+        $printer.out(String::from("\n"))
+    };
+    ($printer:expr, $($arg:tt)*) => {
+        $printer.out(format!("{}\n", format_args!($($arg)*)))
+    };
+}
+
+/// Like `eprint!`, but through a `Printer`.
+macro_rules! err {
+    ($printer:expr, $($arg:tt)*) => {
+        $printer.err(format!($($arg)*))
+    };
+}
+
+/// Like `eprintln!`, but through a `Printer`.
+macro_rules! errln {
+    ($printer:expr, $($arg:tt)*) => {
+        $printer.err(format!("{}\n", format_args!($($arg)*)))
+    };
+}
@@ -0,0 +1,82 @@
+//! Checking `--patch-type` bodies before they are sent.
+
+use crate::error::{self, FetchError};
+use clap::ValueEnum;
+use serde_json::Value;
+
+/// The patch format of a `--patch-type` body
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PatchType {
+    /// JSON Merge Patch (RFC 7386), sent as application/merge-patch+json
+    Merge,
+    /// JSON Patch (RFC 6902), sent as application/json-patch+json
+    Json,
+}
+
+impl PatchType {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            PatchType::Merge => "application/merge-patch+json",
+            PatchType::Json => "application/json-patch+json",
+        }
+    }
+
+    /// Fails with a description of the first problem in `body`.
+    pub fn validate(self, body: &[u8]) -> error::Result<()> {
+        let document: Value = serde_json::from_slice(body)
+            .map_err(|e| FetchError::Parse(format!("Patch body is not JSON: {}", e)))?;
+        match self {
+            // Any JSON value is a valid merge patch
+            PatchType::Merge => Ok(()),
+            PatchType::Json => validate_json_patch(&document)
+                .map_err(|e| FetchError::Parse(format!("Invalid JSON Patch: {}", e))),
+        }
+    }
+}
+
+fn validate_json_patch(document: &Value) -> Result<(), String> {
+    let operations = document
+        .as_array()
+        .ok_or("the document must be an array of operations")?;
+
+    for (index, operation) in operations.iter().enumerate() {
+        let at = format!("operation {}", index);
+        let operation = operation
+            .as_object()
+            .ok_or_else(|| format!("{} is not an object", at))?;
+        let op = operation
+            .get("op")
+            .and_then(Value::as_str)
+            .ok_or_else(|| format!("{} has no \"op\"", at))?;
+
+        let needs_value = match op {
+            "add" | "replace" | "test" => true,
+            "remove" | "move" | "copy" => false,
+            _ => {
+                return Err(format!(
+                    "{} has unknown op {:?}: expected add, remove, replace, move, copy, or test",
+                    at, op
+                ));
+            }
+        };
+        pointer(operation.get("path"))
+            .map_err(|problem| format!("{} ({}) {} \"path\"", at, op, problem))?;
+        if needs_value && !operation.contains_key("value") {
+            return Err(format!("{} ({}) is missing \"value\"", at, op));
+        }
+        if matches!(op, "move" | "copy") {
+            pointer(operation.get("from"))
+                .map_err(|problem| format!("{} ({}) {} \"from\"", at, op, problem))?;
+        }
+    }
+    Ok(())
+}
+
+/// Checks that a `path` or `from` member is a JSON Pointer (RFC 6901).
+fn pointer(value: Option<&Value>) -> Result<(), &'static str> {
+    match value {
+        None => Err("is missing"),
+        Some(Value::String(pointer)) if pointer.is_empty() || pointer.starts_with('/') => Ok(()),
+        Some(_) => Err("has an invalid JSON Pointer in"),
+    }
+}
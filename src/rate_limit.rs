@@ -0,0 +1,122 @@
+//! The `--rate-limit` token bucket, shared by every request in the process.
+//!
+//! The bucket holds a single token, so requests go out evenly spaced rather
+//! than in bursts, whether they run one after another or concurrently.
+
+use std::sync::OnceLock;
+use tokio::sync::Mutex;
+use tokio::time::{Duration, Instant};
+
+static LIMITER: OnceLock<Limiter> = OnceLock::new();
+
+struct Limiter {
+    per_second: f64,
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl Limiter {
+    fn new(per_second: f64) -> Self {
+        Limiter {
+            per_second,
+            bucket: Mutex::new(Bucket {
+                tokens: 1.0,
+                refilled: Instant::now(),
+            }),
+        }
+    }
+
+    async fn acquire(&self) {
+        // The lock is held while sleeping, so waiters are served in order
+        let mut bucket = self.bucket.lock().await;
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.refilled).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.per_second).min(1.0);
+        bucket.refilled = now;
+
+        if bucket.tokens < 1.0 {
+            let wait = (1.0 - bucket.tokens) / self.per_second;
+            tokio::time::sleep(Duration::from_secs_f64(wait)).await;
+            bucket.tokens = 1.0;
+            bucket.refilled = Instant::now();
+        }
+        bucket.tokens -= 1.0;
+    }
+}
+
+/// Limits every request sent from now on to `per_second`.
+pub fn init(per_second: f64) {
+    // Only run() sets the limit, so a second call can't disagree with the first
+    let _ = LIMITER.set(Limiter::new(per_second));
+}
+
+/// Waits until a request may be sent, taking a token. Returns at once when
+/// no limit is set.
+pub async fn acquire() {
+    if let Some(limiter) = LIMITER.get() {
+        limiter.acquire().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[tokio::test(start_paused = true)]
+    async fn spaces_sequential_requests() {
+        let limiter = Limiter::new(4.0);
+        let start = Instant::now();
+
+        // The first token is available at once
+        limiter.acquire().await;
+        assert_eq!(start.elapsed(), Duration::ZERO);
+
+        for n in 1..=3 {
+            limiter.acquire().await;
+            assert_eq!(start.elapsed(), Duration::from_millis(250 * n));
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn does_not_save_up_a_burst() {
+        let limiter = Limiter::new(2.0);
+        limiter.acquire().await;
+
+        // A long idle spell still refills only one token
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        let start = Instant::now();
+        limiter.acquire().await;
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        limiter.acquire().await;
+        assert_eq!(start.elapsed(), Duration::from_millis(500));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn spaces_concurrent_requests() {
+        let limiter = Arc::new(Limiter::new(10.0));
+        let start = Instant::now();
+
+        let tasks: Vec<_> = (0..5)
+            .map(|_| {
+                let limiter = Arc::clone(&limiter);
+                tokio::spawn(async move {
+                    limiter.acquire().await;
+                    start.elapsed()
+                })
+            })
+            .collect();
+        let mut sent = Vec::new();
+        for task in tasks {
+            sent.push(task.await.unwrap());
+        }
+        sent.sort();
+
+        let expected: Vec<_> = (0..5).map(|n| Duration::from_millis(100 * n)).collect();
+        assert_eq!(sent, expected);
+    }
+}
@@ -0,0 +1,128 @@
+//! The `--interactive` session: requests typed one per line, sharing one
+//! client, cookie jar, and set of default headers.
+//!
+//! Each line is `[METHOD] <path or URL> [body]`, with GET assumed when the
+//! method is left out. A few commands manage the session itself; see
+//! [`HELP`].
+
+use crate::error::{self, FetchError};
+use crate::output::Printer;
+use crate::{Cli, RequestSpec};
+use std::io::{IsTerminal, Write};
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+const HELP: &str = "\
+Requests:
+  [METHOD] <path or URL> [body]   e.g. GET /users/1, POST /users {\"name\":\"a\"}
+Session:
+  base <URL>                      set the base URL that paths are joined onto
+  header <Name: value>            send a header with every later request
+  unheader <Name>                 stop sending a session header
+  headers                         list the session headers
+  help                            show this help
+  exit, quit                      end the session (so does Ctrl-D)";
+
+/// Reads requests from stdin until EOF or `exit`, returning the exit code of
+/// the last request sent.
+pub async fn run(
+    cli: &Cli,
+    client: &reqwest::Client,
+    mut base_url: Option<reqwest::Url>,
+    mut headers: Vec<String>,
+) -> error::Result<i32> {
+    let prompt = std::io::stdin().is_terminal();
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let mut exit_code = 0;
+    let mut index = 0;
+
+    loop {
+        if prompt {
+            print!("fetch> ");
+            let _ = std::io::stdout().flush();
+        }
+        let Some(line) = lines
+            .next_line()
+            .await
+            .map_err(|e| FetchError::read("stdin", e))?
+        else {
+            break;
+        };
+        let line = line.trim();
+        let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let rest = rest.trim();
+
+        match command {
+            "" => continue,
+            "exit" | "quit" => break,
+            "help" => println!("{}", HELP),
+            "base" => match reqwest::Url::parse(rest) {
+                Ok(url) => base_url = Some(url),
+                Err(e) => eprintln!("Error: Invalid base URL {:?}: {}", rest, e),
+            },
+            "header" => match crate::parse_header(rest) {
+                Ok((name, _)) => {
+                    // A new value replaces the old one rather than adding to it
+                    headers.retain(|h| !crate::same_header(h, name.as_str()));
+                    headers.push(rest.to_string());
+                }
+                Err(e) => eprintln!("Error: {}", e),
+            },
+            "unheader" => headers.retain(|h| !crate::same_header(h, rest)),
+            "headers" => headers.iter().for_each(|h| println!("{}", h)),
+            _ => {
+                let spec = match parse_request(line, base_url.as_ref(), &headers, cli) {
+                    Ok(spec) => spec,
+                    Err(e) => {
+                        eprintln!("Error: {}", e);
+                        continue;
+                    }
+                };
+                let mut printer = Printer::direct();
+                exit_code = crate::run_spec(cli, client, spec, index, 1, false, &mut printer).await;
+                index += 1;
+            }
+        }
+    }
+    Ok(exit_code)
+}
+
+/// Turns `[METHOD] <path> [body]` into a request.
+fn parse_request(
+    line: &str,
+    base_url: Option<&reqwest::Url>,
+    headers: &[String],
+    cli: &Cli,
+) -> error::Result<RequestSpec> {
+    let (first, rest) = line.split_once(' ').unwrap_or((line, ""));
+    let looks_like_target = first.starts_with('/') || first.contains("://");
+    let (method, rest) = if looks_like_target {
+        ("GET".to_string(), line)
+    } else {
+        (first.to_uppercase(), rest.trim_start())
+    };
+    let (target, body) = rest.split_once(' ').unwrap_or((rest, ""));
+    if target.is_empty() {
+        return Err(FetchError::Usage(format!(
+            "Missing path after {}: expected e.g. `{} /users`",
+            method, method
+        )));
+    }
+    if base_url.is_none() && reqwest::Url::parse(target).is_err() {
+        return Err(FetchError::Usage(format!(
+            "No base URL to join {} onto: set one with `base <URL>` or --base-url",
+            target
+        )));
+    }
+    let body = body.trim();
+
+    Ok(RequestSpec {
+        name: None,
+        url: crate::resolve_url(base_url, target.to_string())?,
+        method,
+        body: (!body.is_empty()).then(|| body.as_bytes().to_vec()),
+        headers: headers.to_vec(),
+        query: cli.query.clone(),
+        output: None,
+        body_file: None,
+    })
+}
@@ -0,0 +1,132 @@
+//! Loading request definitions from JSON, YAML, or TOML files.
+
+use crate::error::{self, FetchError};
+use serde::Deserialize;
+use std::path::Path;
+
+#[derive(Debug, Deserialize)]
+pub struct RequestFile {
+    /// Label printed before the response, e.g. `=== Create user ===`
+    pub name: Option<String>,
+    pub url: String,
+    pub method: String,
+    /// A JSON value sent as-is, or `"@path"` to send a file's contents
+    pub body: Option<serde_json::Value>,
+    pub headers: Option<Vec<String>>,
+    /// The contents of an `@path` body, loaded by [`load`]
+    #[serde(skip)]
+    pub body_contents: Option<Vec<u8>>,
+}
+
+impl RequestFile {
+    /// Loads an `@path` body, resolving the path against `dir`.
+    fn load_body_file(&mut self, dir: &Path) -> error::Result<()> {
+        let Some(reference) = self
+            .body
+            .as_ref()
+            .and_then(|body| body.as_str())
+            .and_then(|body| body.strip_prefix('@'))
+        else {
+            return Ok(());
+        };
+        let path = dir.join(reference);
+        let display = path.display().to_string();
+        let contents = std::fs::read(&path).map_err(|e| FetchError::read(&display, e))?;
+        self.body = None;
+        self.body_contents = Some(contents);
+        Ok(())
+    }
+}
+
+/// A request file holds either a single request or a list run in order
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum RequestFileContent {
+    Single(RequestFile),
+    Batch(Vec<RequestFile>),
+}
+
+/// Reads a request file, substitutes environment variables, and parses it.
+///
+/// `@path` bodies are loaded relative to the request file's directory.
+pub fn load(path: &str) -> error::Result<RequestFileContent> {
+    let content = std::fs::read_to_string(path).map_err(|e| FetchError::read(path, e))?;
+    let mut parsed = parse(path, &substitute_env(&content)?)?;
+
+    let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+    match &mut parsed {
+        RequestFileContent::Single(file) => file.load_body_file(dir)?,
+        RequestFileContent::Batch(files) => {
+            for file in files {
+                file.load_body_file(dir)?;
+            }
+        }
+    }
+    Ok(parsed)
+}
+
+/// Replaces `${VAR}` placeholders with values from the environment.
+///
+/// Fails listing every referenced variable that isn't set, so a template
+/// is never sent with placeholders left in it.
+fn substitute_env(content: &str) -> error::Result<String> {
+    let mut result = String::with_capacity(content.len());
+    let mut missing = Vec::new();
+    let mut rest = content;
+
+    while let Some(start) = rest.find("${") {
+        let Some(len) = rest[start + 2..].find('}') else {
+            break;
+        };
+        let name = &rest[start + 2..start + 2 + len];
+        result.push_str(&rest[..start]);
+        match std::env::var(name) {
+            Ok(value) => result.push_str(&value),
+            Err(_) if !missing.iter().any(|m| m == name) => missing.push(name.to_string()),
+            Err(_) => {}
+        }
+        rest = &rest[start + 3 + len..];
+    }
+    result.push_str(rest);
+
+    if missing.is_empty() {
+        Ok(result)
+    } else {
+        Err(FetchError::Usage(format!(
+            "Undefined environment variable(s): {}",
+            missing.join(", ")
+        )))
+    }
+}
+
+/// Parses a request file, picking the format from its extension.
+///
+/// `.yaml`/`.yml` files are read as YAML, `.toml` as TOML, and `.json` as
+/// JSON; anything else is tried as JSON first and then as YAML. TOML has no
+/// top-level arrays, so TOML files always describe a single request.
+fn parse(path: &str, content: &str) -> error::Result<RequestFileContent> {
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_lowercase);
+
+    let parsed =
+        match extension.as_deref() {
+            Some("yaml" | "yml") => serde_yaml::from_str(content)
+                .map_err(|e| format!("Invalid YAML in {}: {}", path, e)),
+            Some("toml") => toml::from_str(content)
+                .map(RequestFileContent::Single)
+                .map_err(|e| format!("Invalid TOML in {}: {}", path, e)),
+            Some("json") => serde_json::from_str(content)
+                .map_err(|e| format!("Invalid JSON in {}: {}", path, e)),
+            _ => serde_json::from_str(content).or_else(|json_err| {
+                serde_yaml::from_str(content).map_err(|yaml_err| {
+                    format!(
+                        "Cannot parse {} as JSON ({}) or YAML ({})",
+                        path, json_err, yaml_err
+                    )
+                })
+            }),
+        };
+    parsed.map_err(FetchError::Parse)
+}
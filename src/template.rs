@@ -0,0 +1,123 @@
+//! Rendering `--template` strings against a response.
+//!
+//! Placeholders are `{status}`, `{url}`, `{time}` (milliseconds), `{body}`,
+//! `{body.<dotted.path>}`, and `{header.<Name>}`. `{{` and `}}` print
+//! literal braces.
+
+use crate::error::{self, FetchError};
+use reqwest::StatusCode;
+use reqwest::header::HeaderMap;
+use std::time::Duration;
+
+/// The response fields a template can refer to
+pub struct Context<'a> {
+    pub status: StatusCode,
+    pub url: &'a str,
+    pub headers: &'a HeaderMap,
+    pub body: &'a str,
+    pub time: Duration,
+}
+
+enum Segment<'a> {
+    Text(&'a str),
+    Status,
+    Url,
+    Time,
+    Body,
+    BodyPath(&'a str),
+    Header(&'a str),
+}
+
+/// A parsed `--template`, checked before any request is sent
+pub struct Template<'a> {
+    segments: Vec<Segment<'a>>,
+}
+
+impl<'a> Template<'a> {
+    pub fn parse(template: &'a str) -> error::Result<Self> {
+        let mut segments = Vec::new();
+        let mut rest = template;
+
+        while let Some(start) = rest.find(['{', '}']) {
+            segments.push(Segment::Text(&rest[..start]));
+            let tail = &rest[start..];
+            if tail.starts_with("{{") || tail.starts_with("}}") {
+                segments.push(Segment::Text(&tail[..1]));
+                rest = &tail[2..];
+                continue;
+            }
+            if tail.starts_with('}') {
+                return Err(FetchError::Usage(
+                    "Unmatched \"}\" in --template (use \"}}\" for a literal brace)".to_string(),
+                ));
+            }
+            let Some(end) = tail.find('}') else {
+                return Err(FetchError::Usage(
+                    "Unclosed \"{\" in --template (use \"{{\" for a literal brace)".to_string(),
+                ));
+            };
+            segments.push(placeholder(&tail[1..end])?);
+            rest = &tail[end + 1..];
+        }
+        segments.push(Segment::Text(rest));
+        Ok(Template { segments })
+    }
+
+    /// Fills in every placeholder from the response.
+    pub fn render(&self, context: &Context) -> error::Result<String> {
+        let mut out = String::new();
+        let mut json = None;
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => out.push_str(text),
+                Segment::Status => out.push_str(&context.status.as_u16().to_string()),
+                Segment::Url => out.push_str(context.url),
+                Segment::Time => out.push_str(&context.time.as_millis().to_string()),
+                Segment::Body => out.push_str(context.body),
+                // A missing header renders as nothing, like an empty value
+                Segment::Header(name) => {
+                    if let Some(value) = context.headers.get(*name) {
+                        out.push_str(&String::from_utf8_lossy(value.as_bytes()));
+                    }
+                }
+                Segment::BodyPath(path) => {
+                    if json.is_none() {
+                        json = Some(serde_json::from_str(context.body).map_err(|e| {
+                            FetchError::Response(format!("Response body is not JSON: {}", e))
+                        })?);
+                    }
+                    let value = crate::json_path(json.as_ref().expect("parsed above"), path)
+                        .map_err(|segment| {
+                            FetchError::Response(format!(
+                                "Path not found: {} (missing \"{}\")",
+                                path, segment
+                            ))
+                        })?;
+                    out.push_str(&crate::format_json_value(value));
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+fn placeholder(name: &str) -> error::Result<Segment<'_>> {
+    Ok(match name {
+        "status" => Segment::Status,
+        "url" => Segment::Url,
+        "time" => Segment::Time,
+        "body" => Segment::Body,
+        _ => {
+            if let Some(header) = name.strip_prefix("header.") {
+                Segment::Header(header)
+            } else if let Some(path) = name.strip_prefix("body.") {
+                Segment::BodyPath(path)
+            } else {
+                return Err(FetchError::Usage(format!(
+                    "Unknown --template placeholder {{{}}}: expected status, url, time, body, body.<path>, or header.<name>",
+                    name
+                )));
+            }
+        }
+    })
+}